@@ -1,7 +1,23 @@
 #![allow(warnings,unused)]
 // 闭包
-use std::thread;
-use std::time::Duration;
+use std::cell::{Cell, RefCell};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::hash::Hash;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
 
 fn main() {
     // 闭包是一种匿名函数，它可以赋值给变量也可以作为参数传递给其它函数，
@@ -39,6 +55,13 @@ fn main() {
     let result =  factory(3);
     let result1 = result(3);
     println!("{}", result1);
+
+    cacher_test();
+    workout_plan_test();
+    disk_store_test();
+    sync_cacher_test();
+    async_cacher_test();
+    combinator_test();
 }
 fn muuuuu(intensity: u32) -> u32 {
     println!("muuuu.....");
@@ -46,72 +69,221 @@ fn muuuuu(intensity: u32) -> u32 {
     intensity
 }
 fn workout(intensity: u32, random_number: u32) {
-    if intensity < 25 {
-        println!(
-            "今天活力满满，先做 {} 个俯卧撑!",
-            muuuuu(intensity)
-        );
-        println!(
-            "旁边有妹子在看，俯卧撑太low，再来 {} 组卧推!",
-            muuuuu(intensity)
-        );
-    } else if random_number == 3 {
-        println!("昨天练过度了，今天还是休息下吧！");
-    } else {
-        println!(
-            "昨天练过度了，今天干干有氧，跑步 {} 分钟!",
-            muuuuu(intensity)
-        );
+    let mut plan = WorkoutPlan::new(WorkoutRules::default(), muuuuu);
+    print_plan(&plan.plan(intensity, random_number));
+}
+
+fn print_plan(exercises: &[PlannedExercise]) {
+    match exercises {
+        [] => println!("昨天练过度了，今天还是休息下吧！"),
+        exercises => {
+            for e in exercises {
+                println!("{}", e);
+            }
+        }
     }
 }
 
 // 把函数赋值给一个变量
 fn workout1(intensity: u32, random_number: u32) {
     let action = muuuuu;
-    if intensity < 25 {
-        println!(
-            "今天活力满满, 先做 {} 个俯卧撑!",
-            action(intensity)
-        );
-        println!(
-            "旁边有妹子在看，俯卧撑太low, 再来 {} 组卧推!",
-            action(intensity)
-        );
-    } else if random_number == 3 {
-        println!("昨天练过度了，今天还是休息下吧！");
-    } else {
-        println!(
-            "昨天练过度了，今天干干有氧, 跑步 {} 分钟!",
-            action(intensity)
-        );
-    }
+    let mut plan = WorkoutPlan::new(WorkoutRules::default(), action);
+    print_plan(&plan.plan(intensity, random_number));
 }
 // 闭包实现
 // 只要修改闭包 action 的实现即可
 fn workout2(intensity: u32, random_number: u32) {
-    let action = || {
+    let action = |intensity: u32| {
         println!("muuuu.....");
         thread::sleep(Duration::from_secs(22));
         intensity
     };
-    if intensity < 25 {
-        println!(
-            "今天活力满满，先做 {} 个俯卧撑!",
-            action()
-        );
-        println!(
-            "旁边有妹子在看，俯卧撑太low，再来 {} 组卧推!",
-            action()
-        );
-    } else if random_number == 3 {
-        println!("昨天练过度了，今天还是休息下吧！");
-    } else {
-        println!(
-            "昨天练过度了，今天干干有氧，跑步 {} 分钟!",
-            action()
-        );
+    let mut plan = WorkoutPlan::new(WorkoutRules::default(), action);
+    print_plan(&plan.plan(intensity, random_number));
+}
+
+// 规则驱动的健身计划
+// 阈值、休息条件和训练项目都来自规则文件（TOML 或 JSON），
+// 计划以结构化的列表返回，不再直接打印。
+// 昂贵的 muuuuu 计算以闭包的形式注入，每个计划内同一强度只算一次
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+    Reps,
+    Sets,
+    Minutes,
+}
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Reps => write!(f, "个"),
+            Unit::Sets => write!(f, "组"),
+            Unit::Minutes => write!(f, "分钟"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct ExerciseRule {
+    name: String,
+    unit: Unit,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+struct RestRule {
+    // 高强度时，随机值落在其中就休息
+    #[serde(default)]
+    random_numbers: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct WorkoutRules {
+    intensity_threshold: u32,
+    #[serde(default)]
+    rest: RestRule,
+    #[serde(default)]
+    low_intensity: Vec<ExerciseRule>,
+    #[serde(default)]
+    high_intensity: Vec<ExerciseRule>,
+}
+
+#[derive(Debug)]
+enum RulesError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    // 无法根据扩展名判断文件格式
+    UnknownFormat(PathBuf),
+}
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RulesError::Io(e) => write!(f, "读取规则文件失败: {}", e),
+            RulesError::Toml(e) => write!(f, "TOML 格式错误: {}", e),
+            RulesError::Json(e) => write!(f, "JSON 格式错误: {}", e),
+            RulesError::UnknownFormat(p) => write!(f, "不支持的规则文件格式: {}", p.display()),
+        }
+    }
+}
+impl std::error::Error for RulesError {}
+
+impl Default for WorkoutRules {
+    // 与原先硬编码的 workout 行为一致
+    fn default() -> Self {
+        Self {
+            intensity_threshold: 25,
+            rest: RestRule { random_numbers: vec![3] },
+            low_intensity: vec![
+                ExerciseRule { name: "俯卧撑".to_string(), unit: Unit::Reps },
+                ExerciseRule { name: "卧推".to_string(), unit: Unit::Sets },
+            ],
+            high_intensity: vec![ExerciseRule { name: "跑步".to_string(), unit: Unit::Minutes }],
+        }
+    }
+}
+impl WorkoutRules {
+    fn from_toml_str(s: &str) -> Result<WorkoutRules, RulesError> {
+        toml::from_str(s).map_err(RulesError::Toml)
+    }
+
+    fn from_json_str(s: &str) -> Result<WorkoutRules, RulesError> {
+        serde_json::from_str(s).map_err(RulesError::Json)
+    }
+
+    // 根据扩展名选择解析方式
+    fn load(path: impl AsRef<Path>) -> Result<WorkoutRules, RulesError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(RulesError::Io)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml_str(&content),
+            Some("json") => Self::from_json_str(&content),
+            _ => Err(RulesError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PlannedExercise {
+    name: String,
+    amount: u32,
+    unit: Unit,
+}
+impl fmt::Display for PlannedExercise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.name, self.amount, self.unit)
+    }
+}
+
+struct WorkoutPlan<F>
+where
+    F: Fn(u32) -> u32,
+{
+    rules: WorkoutRules,
+    action: Cacher<F, u32, u32>,
+}
+impl<F> WorkoutPlan<F>
+where
+    F: Fn(u32) -> u32,
+{
+    fn new(rules: WorkoutRules, action: F) -> WorkoutPlan<F> {
+        Self { rules, action: Cacher::new(action) }
     }
 
+    // 返回空列表表示今天休息
+    fn plan(&mut self, intensity: u32, random_number: u32) -> Vec<PlannedExercise> {
+        let exercises = if intensity < self.rules.intensity_threshold {
+            &self.rules.low_intensity
+        } else if self.rules.rest.random_numbers.contains(&random_number) {
+            return Vec::new();
+        } else {
+            &self.rules.high_intensity
+        };
+        exercises
+            .iter()
+            .map(|e| PlannedExercise {
+                name: e.name.clone(),
+                amount: self.action.value(intensity),
+                unit: e.unit,
+            })
+            .collect()
+    }
+}
+
+fn workout_plan_test() {
+    let calls = Cell::new(0);
+    let action = |intensity: u32| {
+        calls.set(calls.get() + 1);
+        intensity
+    };
+    let mut plan = WorkoutPlan::new(WorkoutRules::default(), action);
+    assert_eq!(plan.plan(10, 7), vec![
+        PlannedExercise { name: "俯卧撑".to_string(), amount: 10, unit: Unit::Reps },
+        PlannedExercise { name: "卧推".to_string(), amount: 10, unit: Unit::Sets },
+    ]);
+    // 同一强度只计算一次
+    assert_eq!(calls.get(), 1);
+    assert!(plan.plan(30, 3).is_empty());
+    assert_eq!(plan.plan(30, 1)[0].to_string(), "跑步 30 分钟");
+    assert_eq!(calls.get(), 2);
+
+    let rules = WorkoutRules::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("workout.toml")).unwrap();
+    assert_eq!(rules, WorkoutRules::default());
+
+    let rules = WorkoutRules::from_json_str(r#"{
+        "intensity_threshold": 10,
+        "rest": { "random_numbers": [1, 2] },
+        "high_intensity": [{ "name": "游泳", "unit": "minutes" }]
+    }"#).unwrap();
+    let mut plan = WorkoutPlan::new(rules, |i| i * 2);
+    assert!(plan.plan(5, 1).is_empty());
+    assert!(plan.plan(20, 2).is_empty());
+    assert_eq!(plan.plan(20, 3), vec![
+        PlannedExercise { name: "游泳".to_string(), amount: 40, unit: Unit::Minutes },
+    ]);
+
+    assert!(matches!(WorkoutRules::from_toml_str("intensity_threshold = \"high\""), Err(RulesError::Toml(_))));
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/main.rs");
+    assert!(matches!(WorkoutRules::load(src), Err(RulesError::UnknownFormat(_))));
 }
 
 // 多个参数就 |param1, param2,...|
@@ -128,36 +300,1097 @@ fn workout2(intensity: u32, random_number: u32) {
 
 
 // 结构体中的闭包
-// T: Fn(u32) -> u32 意味着 query 的类型是 T，
-// 该类型必须实现了相应的闭包特征 Fn(u32) -> u32
-struct Cacher<T>
-where T: Fn(u32) -> u32,
+// F: Fn(K) -> V 意味着 query 的类型是 F，
+// 该类型必须实现了相应的闭包特征 Fn(K) -> V
+// 缓存以参数 K 为键，每个参数各自缓存一份结果，
+// 不会再出现 value(1) 之后 value(2) 依然返回第一次结果的问题
+
+// 淘汰策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Eviction {
+    // 不淘汰，缓存会一直增长
+    Unbounded,
+    // 容量满时淘汰最久未被使用的条目
+    Lru(usize),
+    // 容量满时淘汰使用次数最少的条目，次数相同时淘汰更旧的
+    Lfu(usize),
+    // 条目超过存活时间后视为失效
+    Ttl(Duration),
+}
+
+// 命中、未命中、淘汰次数的统计
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct CacheStats {
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    // 内存未命中、但从后备存储中取回的次数
+    store_hits: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    // 在 Order 中的位置，用于 LRU/LFU
+    slot: usize,
+    // 写入时间，用于 TTL
+    inserted: Instant,
+}
+
+// 淘汰顺序：若干个按“桶”划分的双向链表，节点放在 Vec 里用下标互相指向，
+// 访问、插入、淘汰都是 O(1)。
+// LRU 只用一个桶；LFU 按使用次数分桶，桶内按最近访问排序，次数相同时淘汰更旧的
+struct OrderNode<K> {
+    key: K,
+    bucket: u64,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+struct Order<K> {
+    nodes: Vec<Option<OrderNode<K>>>,
+    free: Vec<usize>,
+    // 桶 -> (最旧, 最新)
+    buckets: HashMap<u64, (usize, usize)>,
+    // 当前最小的非空桶
+    min_bucket: u64,
+}
+
+impl<K> Order<K> {
+    fn new() -> Order<K> {
+        Order { nodes: Vec::new(), free: Vec::new(), buckets: HashMap::new(), min_bucket: 0 }
+    }
+
+    fn node(&mut self, slot: usize) -> &mut OrderNode<K> {
+        self.nodes[slot].as_mut().unwrap()
+    }
+
+    // 接到桶的末尾（最新）
+    fn link(&mut self, slot: usize, bucket: u64) {
+        let tail = self.buckets.get(&bucket).map(|&(_, tail)| tail);
+        let node = self.node(slot);
+        node.bucket = bucket;
+        node.prev = tail;
+        node.next = None;
+        match tail {
+            Some(tail) => {
+                self.node(tail).next = Some(slot);
+                self.buckets.get_mut(&bucket).unwrap().1 = slot;
+            }
+            None => {
+                self.buckets.insert(bucket, (slot, slot));
+            }
+        }
+    }
+
+    // 从所在的桶里摘下来，返回摘下后桶是否空了
+    fn unlink(&mut self, slot: usize) -> bool {
+        let node = self.node(slot);
+        let (bucket, prev, next) = (node.bucket, node.prev, node.next);
+        match prev {
+            Some(p) => self.node(p).next = next,
+            None => match next {
+                Some(n) => self.buckets.get_mut(&bucket).unwrap().0 = n,
+                None => {
+                    self.buckets.remove(&bucket);
+                    return true;
+                }
+            },
+        }
+        match next {
+            Some(n) => self.node(n).prev = prev,
+            None => self.buckets.get_mut(&bucket).unwrap().1 = prev.unwrap(),
+        }
+        false
+    }
+
+    fn insert(&mut self, key: K, bucket: u64) -> usize {
+        let node = OrderNode { key, bucket, prev: None, next: None };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.link(slot, bucket);
+        if self.buckets.len() == 1 || bucket < self.min_bucket {
+            self.min_bucket = bucket;
+        }
+        slot
+    }
+
+    // 访问一次：移到 bucket 桶的末尾
+    fn touch(&mut self, slot: usize, bucket: u64) {
+        let old = self.node(slot).bucket;
+        let emptied = self.unlink(slot);
+        self.link(slot, bucket);
+        if emptied && old == self.min_bucket {
+            self.min_bucket = bucket;
+        }
+    }
+
+    fn bucket_of(&self, slot: usize) -> u64 {
+        self.nodes[slot].as_ref().unwrap().bucket
+    }
+
+    // 淘汰最小桶里最旧的一个
+    fn pop(&mut self) -> Option<K> {
+        let &(head, _) = self.buckets.get(&self.min_bucket)?;
+        if self.unlink(head) {
+            // 只有连续淘汰多个时才会走到这里
+            self.min_bucket = self.buckets.keys().copied().min().unwrap_or(0);
+        }
+        self.free.push(head);
+        self.nodes[head].take().map(|n| n.key)
+    }
+
+    fn remove(&mut self, slot: usize) {
+        let bucket = self.bucket_of(slot);
+        if self.unlink(slot) && bucket == self.min_bucket {
+            self.min_bucket = self.buckets.keys().copied().min().unwrap_or(0);
+        }
+        self.nodes[slot] = None;
+        self.free.push(slot);
+    }
+
+    fn clear(&mut self) {
+        *self = Order::new();
+    }
+}
+
+struct Cacher<F, K, V>
+where
+    F: Fn(K) -> V,
+    K: Hash + Eq + Clone,
+    V: Clone,
 {
-    query: T,
-    value: Option<u32>,
+    query: F,
+    values: HashMap<K, Entry<V>>,
+    policy: Eviction,
+    order: Order<K>,
+    stats: CacheStats,
+    // 可选的第二层存储，内存未命中时先查它，再调用 query
+    store: Option<Box<dyn BackingStore<K, V>>>,
 }
-impl<T> Cacher<T>
-where T: Fn(u32) -> u32,
+impl<F, K, V> Cacher<F, K, V>
+where
+    F: Fn(K) -> V,
+    K: Hash + Eq + Clone,
+    V: Clone,
 {
-    fn new(query: T) -> Cacher<T> {
+    fn new(query: F) -> Cacher<F, K, V> {
+        Self::with_policy(query, Eviction::Unbounded)
+    }
+
+    fn with_policy(query: F, policy: Eviction) -> Cacher<F, K, V> {
         Self {
             query,
-            value: None,
+            values: HashMap::new(),
+            policy,
+            order: Order::new(),
+            stats: CacheStats::default(),
+            store: None,
         }
     }
-    // 先查询缓存值 `self.value`，若不存在，则调用 `query` 加载
-    fn value(&mut self, arg:u32) -> u32 {
-        match self.value {
-            Some(v) => v,
+
+    fn with_store(mut self, store: impl BackingStore<K, V> + 'static) -> Cacher<F, K, V> {
+        self.store = Some(Box::new(store));
+        self
+    }
+
+    // 先查询缓存中 `arg` 对应的值，若不存在或已过期，则调用 `query` 加载
+    fn value(&mut self, arg: K) -> V {
+        let expired = match (self.policy, self.values.get(&arg)) {
+            (Eviction::Ttl(ttl), Some(e)) => e.inserted.elapsed() >= ttl,
+            _ => false,
+        };
+        if expired {
+            self.values.remove(&arg);
+            self.stats.evictions += 1;
+        }
+        if let Some(e) = self.values.get(&arg) {
+            match self.policy {
+                Eviction::Lru(_) => self.order.touch(e.slot, 0),
+                Eviction::Lfu(_) => self.order.touch(e.slot, self.order.bucket_of(e.slot) + 1),
+                _ => {}
+            }
+            self.stats.hits += 1;
+            return e.value.clone();
+        }
+
+        self.stats.misses += 1;
+        // 刚过期的条目不从后备存储取回，否则会拿到同一个旧值
+        let stored = match &mut self.store {
+            Some(store) if !expired => store.get(&arg),
+            _ => None,
+        };
+        let v = match stored {
+            Some(v) => {
+                self.stats.store_hits += 1;
+                v
+            }
             None => {
-                let v = (self.query)(arg);
-                self.value = Some(v);
+                let v = (self.query)(arg.clone());
+                if let Some(store) = &mut self.store {
+                    // 后备存储只是加速手段，写入失败不影响返回结果
+                    let _ = store.put(&arg, &v);
+                }
                 v
-            },
+            }
+        };
+        // 容量为 0 时不缓存
+        if !self.make_room() {
+            return v;
+        }
+        let slot = match self.policy {
+            Eviction::Lru(_) => self.order.insert(arg.clone(), 0),
+            Eviction::Lfu(_) => self.order.insert(arg.clone(), 1),
+            _ => 0,
+        };
+        self.values.insert(arg, Entry {
+            value: v.clone(),
+            slot,
+            inserted: Instant::now(),
+        });
+        v
+    }
+
+    // 插入新条目前，按照淘汰策略腾出空间，返回能否插入
+    fn make_room(&mut self) -> bool {
+        match self.policy {
+            Eviction::Unbounded => true,
+            Eviction::Lru(0) | Eviction::Lfu(0) => false,
+            Eviction::Lru(capacity) | Eviction::Lfu(capacity) => {
+                while self.values.len() >= capacity {
+                    match self.order.pop() {
+                        Some(k) => {
+                            self.values.remove(&k);
+                            self.stats.evictions += 1;
+                        }
+                        None => break,
+                    }
+                }
+                true
+            }
+            Eviction::Ttl(ttl) => {
+                let before = self.values.len();
+                self.values.retain(|_, e| e.inserted.elapsed() < ttl);
+                self.stats.evictions += (before - self.values.len()) as u64;
+                true
+            }
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn contains(&self, arg: &K) -> bool {
+        self.values.contains_key(arg)
+    }
+
+    fn clear(&mut self) {
+        self.values.clear();
+        self.order.clear();
+    }
+}
+
+fn cacher_test() {
+    // 不同参数各自缓存
+    let mut c = Cacher::new(|a: u32| a * 2);
+    assert_eq!(c.value(1), 2);
+    assert_eq!(c.value(2), 4);
+    assert_eq!(c.value(1), 2);
+    assert_eq!(c.stats(), CacheStats { hits: 1, misses: 2, evictions: 0, store_hits: 0 });
+
+    // 任意 K: Hash + Eq，V: Clone
+    let mut c = Cacher::new(|s: String| s.len());
+    assert_eq!(c.value("hello".to_string()), 5);
+    assert_eq!(c.value("hi".to_string()), 2);
+
+    // LRU：访问过的 1 保留，最久未用的 2 被淘汰
+    let mut c = Cacher::with_policy(|a: u32| a, Eviction::Lru(2));
+    c.value(1);
+    c.value(2);
+    c.value(1);
+    c.value(3);
+    assert!(c.contains(&1) && c.contains(&3) && !c.contains(&2));
+    assert_eq!(c.stats().evictions, 1);
+
+    // LFU：使用次数最少的 2 被淘汰
+    let mut c = Cacher::with_policy(|a: u32| a, Eviction::Lfu(2));
+    c.value(1);
+    c.value(1);
+    c.value(2);
+    c.value(3);
+    assert!(c.contains(&1) && c.contains(&3) && !c.contains(&2));
+    // 次数相同时淘汰更久没用的：3 用了 2 次，1 还是 2 次但更旧
+    c.value(3);
+    c.value(4);
+    assert!(c.contains(&3) && c.contains(&4) && !c.contains(&1));
+
+    // 较大的容量下反复访问，淘汰顺序保持正确
+    let mut c = Cacher::with_policy(|a: u32| a, Eviction::Lru(100));
+    for i in 0..1000 {
+        c.value(i);
+        c.value(i / 2);
+    }
+    assert_eq!(c.len(), 100);
+    assert!(c.contains(&999) && c.contains(&499) && !c.contains(&0));
+    c.clear();
+    assert_eq!(c.len(), 0);
+    c.value(1);
+    assert_eq!(c.len(), 1);
+
+    // 容量为 0 时每次都调用 query，什么也不存
+    let calls = Cell::new(0);
+    let mut c = Cacher::with_policy(|a: u32| { calls.set(calls.get() + 1); a }, Eviction::Lru(0));
+    c.value(1);
+    c.value(1);
+    assert_eq!((c.len(), calls.get()), (0, 2));
+    let mut c = Cacher::with_policy(|a: u32| a, Eviction::Lfu(0));
+    c.value(1);
+    assert_eq!(c.len(), 0);
+
+    // TTL：过期后重新调用 query
+    let mut c = Cacher::with_policy(|a: u32| a, Eviction::Ttl(Duration::from_millis(10)));
+    c.value(1);
+    thread::sleep(Duration::from_millis(20));
+    c.value(1);
+    assert_eq!(c.stats(), CacheStats { hits: 0, misses: 2, evictions: 1, store_hits: 0 });
+    assert_eq!(c.len(), 1);
+}
+
+// 磁盘后备存储
+// 进程退出后内存里的缓存就没了，DiskStore 把结果追加写入本地文件，
+// 启动时重新加载。每条记录的格式为：
+// [magic: 4 字节][payload 长度: u32][校验和: u32][payload = key + value]
+// 某条记录损坏时，加载器向后寻找下一个 magic 重新对齐，后面完好的记录不受影响
+// 校验失败的记录会被当作不存在，文件损坏只会退化为缓存未命中，不会 panic
+// 注意磁盘层不记录写入时间，TTL 只作用于内存中的条目
+trait BackingStore<K, V> {
+    fn get(&mut self, key: &K) -> Option<V>;
+    fn put(&mut self, key: &K, value: &V) -> io::Result<()>;
+}
+
+// 键和值的二进制编解码
+trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    // 返回解出的值以及消耗的字节数
+    fn decode(buf: &[u8]) -> Option<(Self, usize)>;
+}
+impl Codec for u32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+    fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        Some((u32::from_le_bytes(buf.get(..4)?.try_into().ok()?), 4))
+    }
+}
+impl Codec for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+    fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        Some((u64::from_le_bytes(buf.get(..8)?.try_into().ok()?), 8))
+    }
+}
+impl Codec for i32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+    fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        Some((i32::from_le_bytes(buf.get(..4)?.try_into().ok()?), 4))
+    }
+}
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        let (len, n) = u32::decode(buf)?;
+        let bytes = buf.get(n..n + len as usize)?;
+        Some((String::from_utf8(bytes.to_vec()).ok()?, n + len as usize))
+    }
+}
+
+// FNV-1a 校验和
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5u32, |h, b| (h ^ *b as u32).wrapping_mul(0x01000193))
+}
+
+const RECORD_MAGIC: [u8; 4] = *b"CREC";
+const RECORD_HEADER: u64 = 12;
+
+// 编码一条完整的记录
+fn encode_record(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER as usize + payload.len());
+    record.extend_from_slice(&RECORD_MAGIC);
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&checksum(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+// 从 buf 开头解析一条记录，magic、长度、校验和任何一项不对都返回 None
+fn parse_record(buf: &[u8]) -> Option<&[u8]> {
+    let header = buf.get(..RECORD_HEADER as usize)?;
+    if header[..4] != RECORD_MAGIC {
+        return None;
+    }
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    let sum = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let payload = buf.get(RECORD_HEADER as usize..RECORD_HEADER as usize + len)?;
+    (checksum(payload) == sum).then_some(payload)
+}
+
+struct DiskStore<K, V> {
+    path: PathBuf,
+    file: File,
+    // key -> 最新一条记录在文件中的偏移和 payload 长度
+    index: HashMap<K, (u64, u32)>,
+    // 文件中的有效记录数（含被覆盖的旧记录），用于判断是否需要压缩
+    records: usize,
+    // 加载时发现的损坏记录数
+    corrupt: usize,
+    len: u64,
+    _value: PhantomData<V>,
+}
+impl<K, V> DiskStore<K, V>
+where
+    K: Codec + Hash + Eq + Clone,
+    V: Codec,
+{
+    fn open(path: impl Into<PathBuf>) -> io::Result<DiskStore<K, V>> {
+        let path = path.into();
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+
+        let mut index = HashMap::new();
+        // corrupt 记的是跳过的损坏片段数，连续的坏数据算一处
+        let (mut records, mut corrupt) = (0, 0);
+        let mut pos = 0usize;
+        // 最后一条完好记录的结尾
+        let mut end = 0usize;
+        let mut in_bad = false;
+        while pos < buf.len() {
+            let parsed = parse_record(&buf[pos..]).and_then(|payload| Some((K::decode(payload)?.0, payload.len())));
+            match parsed {
+                Some((key, len)) => {
+                    index.insert(key, (pos as u64, len as u32));
+                    records += 1;
+                    pos += RECORD_HEADER as usize + len;
+                    end = pos;
+                    in_bad = false;
+                }
+                None => {
+                    if !in_bad {
+                        corrupt += 1;
+                        in_bad = true;
+                    }
+                    // 向后找下一个 magic，找不到说明后面都是坏的
+                    match buf[pos + 1..].windows(RECORD_MAGIC.len()).position(|w| w == RECORD_MAGIC) {
+                        Some(i) => pos += 1 + i,
+                        None => break,
+                    }
+                }
+            }
+        }
+        // 只截掉最后一条完好记录之后的部分（写了一半的记录或垃圾数据），
+        // 保证后续追加的记录紧跟在有效数据后面
+        if end < buf.len() {
+            file.set_len(end as u64)?;
+        }
+        Ok(Self { path, file, index, records, corrupt, len: end as u64, _value: PhantomData })
+    }
+
+    fn read_record(&mut self, offset: u64, len: u32) -> Option<Vec<u8>> {
+        let mut buf = vec![0; RECORD_HEADER as usize + len as usize];
+        self.file.seek(SeekFrom::Start(offset)).ok()?;
+        self.file.read_exact(&mut buf).ok()?;
+        parse_record(&buf).map(|payload| payload.to_vec())
+    }
+
+    // 只保留每个 key 最新的一条记录，写到临时文件后替换原文件
+    fn compact(&mut self) -> io::Result<()> {
+        let tmp = self.path.with_extension("compact");
+        let mut out = Vec::new();
+        let mut index = HashMap::new();
+        let entries: Vec<_> = self.index.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        for (key, (offset, len)) in entries {
+            if let Some(payload) = self.read_record(offset, len) {
+                index.insert(key, (out.len() as u64, len));
+                out.extend_from_slice(&encode_record(&payload));
+            }
+        }
+        let mut f = File::create(&tmp)?;
+        f.write_all(&out)?;
+        f.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        self.file = OpenOptions::new().read(true).append(true).open(&self.path)?;
+        self.records = index.len();
+        self.index = index;
+        self.len = out.len() as u64;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn records(&self) -> usize {
+        self.records
+    }
+
+    fn corrupt(&self) -> usize {
+        self.corrupt
+    }
+}
+impl<K, V> BackingStore<K, V> for DiskStore<K, V>
+where
+    K: Codec + Hash + Eq + Clone,
+    V: Codec,
+{
+    fn get(&mut self, key: &K) -> Option<V> {
+        let (offset, len) = *self.index.get(key)?;
+        let decoded = self.read_record(offset, len).and_then(|payload| {
+            let (_, n) = K::decode(&payload)?;
+            V::decode(&payload[n..]).map(|(v, _)| v)
+        });
+        if decoded.is_none() {
+            // 读出来是坏的，当作不存在
+            self.index.remove(key);
+        }
+        decoded
+    }
+
+    fn put(&mut self, key: &K, value: &V) -> io::Result<()> {
+        let mut payload = Vec::new();
+        key.encode(&mut payload);
+        value.encode(&mut payload);
+        let record = encode_record(&payload);
+        self.file.write_all(&record)?;
+
+        self.index.insert(key.clone(), (self.len, payload.len() as u32));
+        self.len += record.len() as u64;
+        self.records += 1;
+        // 旧记录超过一半时自动压缩
+        if self.records >= 64 && self.records > self.index.len() * 2 {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+fn disk_store_test() {
+    let path = env::temp_dir().join(format!("rust32_1_cacher_{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let calls = Rc::new(Cell::new(0));
+
+    let counter = Rc::clone(&calls);
+    let query = move |a: u32| {
+        counter.set(counter.get() + 1);
+        format!("muuuu{}", a)
+    };
+    {
+        let mut c = Cacher::new(query.clone()).with_store(DiskStore::open(&path).unwrap());
+        assert_eq!(c.value(1), "muuuu1");
+        assert_eq!(c.value(2), "muuuu2");
+    }
+    assert_eq!(calls.get(), 2);
+
+    // 重新启动后从磁盘加载，不再调用 query
+    let mut c = Cacher::new(query.clone()).with_store(DiskStore::open(&path).unwrap());
+    assert_eq!(c.value(1), "muuuu1");
+    assert_eq!(calls.get(), 2);
+    assert_eq!(c.stats().store_hits, 1);
+    drop(c);
+
+    // 压缩后只保留每个 key 最新的记录
+    let mut store: DiskStore<u32, String> = DiskStore::open(&path).unwrap();
+    store.put(&1, &"new".to_string()).unwrap();
+    assert_eq!(store.records(), 3);
+    store.compact().unwrap();
+    assert_eq!((store.len(), store.records()), (2, 2));
+    assert_eq!(store.get(&1), Some("new".to_string()));
+    drop(store);
+
+    // 篡改文件内容后，损坏的记录退化为未命中
+    let mut bytes = fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    bytes.extend_from_slice(&[1, 2, 3]);
+    fs::write(&path, &bytes).unwrap();
+    let store: DiskStore<u32, String> = DiskStore::open(&path).unwrap();
+    assert_eq!(store.corrupt(), 1);
+    let mut c = Cacher::new(query).with_store(store);
+    c.value(1);
+    c.value(2);
+    assert_eq!(calls.get(), 3);
+    drop(c);
+    let _ = fs::remove_file(&path);
+
+    // 中间某条记录的长度字段坏了，后面的记录照样能加载，文件不会被截短
+    let mut store: DiskStore<u32, u32> = DiskStore::open(&path).unwrap();
+    for i in 0..3 {
+        store.put(&i, &(i * 100)).unwrap();
+    }
+    drop(store);
+    let mut bytes = fs::read(&path).unwrap();
+    let size = bytes.len();
+    bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    let mut store: DiskStore<u32, u32> = DiskStore::open(&path).unwrap();
+    assert_eq!((store.corrupt(), store.len()), (1, 2));
+    assert_eq!(store.get(&0), None);
+    assert_eq!(store.get(&2), Some(200));
+    assert_eq!(fs::metadata(&path).unwrap().len(), size as u64);
+    drop(store);
+
+    // 只写了一半的最后一条记录会被截掉，之前的记录保留，之后还能继续追加
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.extend_from_slice(&encode_record(&[1, 2, 3, 4, 5, 6, 7, 8])[..10]);
+    fs::write(&path, &bytes).unwrap();
+    let mut store: DiskStore<u32, u32> = DiskStore::open(&path).unwrap();
+    assert_eq!((store.corrupt(), store.len()), (2, 2));
+    assert_eq!(fs::metadata(&path).unwrap().len(), size as u64);
+    store.put(&7, &700).unwrap();
+    drop(store);
+    let mut store: DiskStore<u32, u32> = DiskStore::open(&path).unwrap();
+    assert_eq!(store.get(&7), Some(700));
+    assert_eq!(store.get(&1), Some(100));
+    drop(store);
+    let _ = fs::remove_file(&path);
+}
+
+// 多线程共享的缓存
+// Cacher::value 需要 &mut self，只能在一个线程里使用。
+// SyncCacher 内部用 Mutex 保护缓存表，可以放在 Arc 里跨线程共享，
+// 同一个 key 的并发请求只会触发一次 query，其余线程阻塞等待这次计算的结果
+enum Slot<V> {
+    Ready(V),
+    // 正在计算中，等待者在 Condvar 上阻塞
+    Pending(Arc<Flight<V>>),
+}
+
+struct Flight<V> {
+    // None 表示还没算完；Some(None) 表示计算线程 panic 了，等待者需要重试
+    result: Mutex<Option<Option<V>>>,
+    done: Condvar,
+}
+
+struct SyncCacher<F, K, V>
+where
+    F: Fn(K) -> V + Send + Sync,
+    K: Hash + Eq + Clone + Send,
+    V: Clone + Send,
+{
+    query: F,
+    slots: Mutex<HashMap<K, Slot<V>>>,
+    computations: AtomicU64,
+    // 被合并到已有计算上的请求数
+    coalesced: AtomicU64,
+}
+
+// 计算线程持有的守卫，若 query panic，drop 时会移除 Pending 条目并唤醒等待者
+struct FlightGuard<'a, K: Hash + Eq, V> {
+    slots: &'a Mutex<HashMap<K, Slot<V>>>,
+    key: Option<K>,
+    flight: Arc<Flight<V>>,
+}
+impl<'a, K: Hash + Eq, V> Drop for FlightGuard<'a, K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(Slot::Pending(f)) = slots.get(&key) {
+                if Arc::ptr_eq(f, &self.flight) {
+                    slots.remove(&key);
+                }
+            }
+            *self.flight.result.lock().unwrap_or_else(|e| e.into_inner()) = Some(None);
+            self.flight.done.notify_all();
+        }
+    }
+}
+
+impl<F, K, V> SyncCacher<F, K, V>
+where
+    F: Fn(K) -> V + Send + Sync,
+    K: Hash + Eq + Clone + Send,
+    V: Clone + Send,
+{
+    fn new(query: F) -> SyncCacher<F, K, V> {
+        Self {
+            query,
+            slots: Mutex::new(HashMap::new()),
+            computations: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+
+    fn value(&self, arg: K) -> V {
+        loop {
+            let flight = {
+                let mut slots = self.slots.lock().unwrap();
+                match slots.get(&arg) {
+                    Some(Slot::Ready(v)) => return v.clone(),
+                    Some(Slot::Pending(f)) => {
+                        self.coalesced.fetch_add(1, Ordering::Relaxed);
+                        Arc::clone(f)
+                    }
+                    None => {
+                        let flight = Arc::new(Flight {
+                            result: Mutex::new(None),
+                            done: Condvar::new(),
+                        });
+                        slots.insert(arg.clone(), Slot::Pending(Arc::clone(&flight)));
+                        drop(slots);
+                        return self.compute(arg, flight);
+                    }
+                }
+            };
+            // 等待正在进行的计算，拿到结果直接返回，计算失败则重新抢占
+            let mut result = flight.result.lock().unwrap();
+            while result.is_none() {
+                result = flight.done.wait(result).unwrap();
+            }
+            if let Some(Some(v)) = result.as_ref() {
+                return v.clone();
+            }
+        }
+    }
+
+    // 在锁外调用 query，算完后写回缓存并唤醒等待者
+    fn compute(&self, arg: K, flight: Arc<Flight<V>>) -> V {
+        let mut guard = FlightGuard {
+            slots: &self.slots,
+            key: Some(arg.clone()),
+            flight: Arc::clone(&flight),
+        };
+        self.computations.fetch_add(1, Ordering::Relaxed);
+        let v = (self.query)(arg);
+        let key = guard.key.take().unwrap();
+
+        let mut slots = self.slots.lock().unwrap();
+        // 计算期间若被 invalidate，就不再写回缓存
+        if let Some(Slot::Pending(f)) = slots.get(&key) {
+            if Arc::ptr_eq(f, &flight) {
+                slots.insert(key, Slot::Ready(v.clone()));
+            }
+        }
+        drop(slots);
+        *flight.result.lock().unwrap() = Some(Some(v.clone()));
+        flight.done.notify_all();
+        v
+    }
+
+    // 让某个 key 失效，下一次访问会重新计算
+    fn invalidate(&self, arg: &K) -> bool {
+        self.slots.lock().unwrap().remove(arg).is_some()
+    }
+
+    fn computations(&self) -> u64 {
+        self.computations.load(Ordering::Relaxed)
+    }
+
+    fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+fn sync_cacher_test() {
+    // query 卡在 gate 上，直到 8 个请求都已经发出
+    let gate = Arc::new((Mutex::new(false), Condvar::new()));
+    let query_gate = Arc::clone(&gate);
+    let cacher = Arc::new(SyncCacher::new(move |a: u32| {
+        let (open, cvar) = &*query_gate;
+        let mut open = open.lock().unwrap();
+        while !*open {
+            open = cvar.wait(open).unwrap();
+        }
+        a * 10
+    }));
+    let barrier = Arc::new(std::sync::Barrier::new(8));
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let cacher = Arc::clone(&cacher);
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                cacher.value(3)
+            })
+        })
+        .collect();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while cacher.coalesced() < 7 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(1));
+    }
+    *gate.0.lock().unwrap() = true;
+    gate.1.notify_all();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 30);
+    }
+    // 8 个线程只触发了一次计算，其余 7 个都合并到了这次计算上
+    assert_eq!(cacher.computations(), 1);
+    assert_eq!(cacher.coalesced(), 7);
+
+    assert_eq!(cacher.value(4), 40);
+    assert_eq!(cacher.computations(), 2);
+    assert!(cacher.invalidate(&3));
+    assert!(!cacher.invalidate(&3));
+    assert_eq!(cacher.value(3), 30);
+    assert_eq!(cacher.computations(), 3);
+}
+
+// 异步缓存
+// query 返回一个 Future，缓存的是 Future 完成后的值。
+// 同一个 key 的多个等待者共享同一个未完成的 Future，
+// Future 返回 Err 时不会写入缓存，下次访问会重新计算
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+struct Inflight<V, E> {
+    // 正在被某个等待者驱动时会被临时取走
+    future: Option<LocalBoxFuture<Result<V, E>>>,
+    result: Option<Result<V, E>>,
+    wakers: Vec<Waker>,
+}
+
+enum AsyncSlot<V, E> {
+    Ready(V),
+    Pending(Rc<RefCell<Inflight<V, E>>>),
+}
+
+// 等待共享 Future 结果的句柄，每个等待者持有一个
+struct Shared<V, E> {
+    flight: Rc<RefCell<Inflight<V, E>>>,
+}
+impl<V: Clone, E: Clone> Future for Shared<V, E> {
+    type Output = Result<V, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut inflight = self.flight.borrow_mut();
+        if let Some(r) = &inflight.result {
+            return Poll::Ready(r.clone());
+        }
+        if let Some(mut fut) = inflight.future.take() {
+            // 驱动内部 Future 时不能持有 RefCell 的借用
+            drop(inflight);
+            let poll = fut.as_mut().poll(cx);
+            inflight = self.flight.borrow_mut();
+            match poll {
+                Poll::Ready(r) => {
+                    inflight.result = Some(r.clone());
+                    for waker in inflight.wakers.drain(..) {
+                        waker.wake();
+                    }
+                    return Poll::Ready(r);
+                }
+                Poll::Pending => inflight.future = Some(fut),
+            }
+        }
+        if !inflight.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            inflight.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+impl<V, E> Drop for Shared<V, E> {
+    // 被丢弃的等待者可能正是内部 Future 唯一会唤醒的那个，
+    // 唤醒其余等待者，让它们接着驱动
+    fn drop(&mut self) {
+        if let Ok(mut inflight) = self.flight.try_borrow_mut() {
+            if inflight.result.is_none() {
+                for waker in inflight.wakers.drain(..) {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+struct AsyncCacher<F, K, V, E, Fut>
+where
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Result<V, E>> + 'static,
+    K: Hash + Eq + Clone,
+    V: Clone,
+    E: Clone,
+{
+    query: F,
+    slots: RefCell<HashMap<K, AsyncSlot<V, E>>>,
+}
+impl<F, K, V, E, Fut> AsyncCacher<F, K, V, E, Fut>
+where
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Result<V, E>> + 'static,
+    K: Hash + Eq + Clone,
+    V: Clone,
+    E: Clone,
+{
+    fn new(query: F) -> AsyncCacher<F, K, V, E, Fut> {
+        Self {
+            query,
+            slots: RefCell::new(HashMap::new()),
+        }
+    }
+
+    async fn value(&self, arg: K) -> Result<V, E> {
+        let flight = {
+            let mut slots = self.slots.borrow_mut();
+            match slots.get(&arg) {
+                Some(AsyncSlot::Ready(v)) => return Ok(v.clone()),
+                Some(AsyncSlot::Pending(f)) => Rc::clone(f),
+                None => {
+                    let flight = Rc::new(RefCell::new(Inflight {
+                        future: Some(Box::pin((self.query)(arg.clone())) as LocalBoxFuture<_>),
+                        result: None,
+                        wakers: Vec::new(),
+                    }));
+                    slots.insert(arg.clone(), AsyncSlot::Pending(Rc::clone(&flight)));
+                    flight
+                }
+            }
+        };
+        let result = Shared { flight: Rc::clone(&flight) }.await;
+
+        // 第一个拿到结果的等待者负责更新缓存
+        let mut slots = self.slots.borrow_mut();
+        if let Some(AsyncSlot::Pending(f)) = slots.get(&arg) {
+            if Rc::ptr_eq(f, &flight) {
+                match &result {
+                    Ok(v) => { slots.insert(arg, AsyncSlot::Ready(v.clone())); }
+                    Err(_) => { slots.remove(&arg); }
+                }
+            }
+        }
+        result
+    }
+
+    fn invalidate(&self, arg: &K) -> bool {
+        self.slots.borrow_mut().remove(arg).is_some()
+    }
+
+    fn is_cached(&self, arg: &K) -> bool {
+        matches!(self.slots.borrow().get(arg), Some(AsyncSlot::Ready(_)))
+    }
+}
+
+// 最小的执行器：在当前线程上轮询 Future，Pending 时 park 等待唤醒
+struct ThreadWaker(Thread);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+fn block_on<T>(fut: impl Future<Output = T>) -> T {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(v) => return v,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+// 并发等待一组 Future，按传入顺序返回结果
+struct JoinAll<T> {
+    futures: Vec<Option<LocalBoxFuture<T>>>,
+    results: Vec<Option<T>>,
+}
+fn join_all<T>(futures: Vec<LocalBoxFuture<T>>) -> JoinAll<T> {
+    let results = futures.iter().map(|_| None).collect();
+    JoinAll { futures: futures.into_iter().map(Some).collect(), results }
+}
+// 子 Future 都已经装箱固定，结果只会被移动，不需要结构化固定
+impl<T> Unpin for JoinAll<T> {}
+impl<T> Future for JoinAll<T> {
+    type Output = Vec<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        for (slot, result) in this.futures.iter_mut().zip(this.results.iter_mut()) {
+            if let Some(fut) = slot {
+                if let Poll::Ready(v) = fut.as_mut().poll(cx) {
+                    *result = Some(v);
+                    *slot = None;
+                }
+            }
+        }
+        if this.futures.iter().all(Option::is_none) {
+            Poll::Ready(this.results.iter_mut().map(|r| r.take().unwrap()).collect())
+        } else {
+            Poll::Pending
         }
     }
 }
 
+// 让出一次执行权，用来模拟需要等待的异步操作
+struct YieldNow(bool);
+fn yield_now() -> YieldNow {
+    YieldNow(false)
+}
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+fn async_cacher_test() {
+    let calls = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&calls);
+    let cacher = Rc::new(AsyncCacher::new(move |a: u32| {
+        counter.set(counter.get() + 1);
+        async move {
+            yield_now().await;
+            if a == 0 { Err("zero".to_string()) } else { Ok(a * 2) }
+        }
+    }));
+
+    // 三个并发的等待者共享同一次计算
+    let futures: Vec<LocalBoxFuture<Result<u32, String>>> = (0..3)
+        .map(|_| {
+            let cacher = Rc::clone(&cacher);
+            Box::pin(async move { cacher.value(5).await }) as LocalBoxFuture<_>
+        })
+        .collect();
+    let results = block_on(join_all(futures));
+    assert_eq!(results, vec![Ok(10), Ok(10), Ok(10)]);
+    assert_eq!(calls.get(), 1);
+    assert!(cacher.is_cached(&5));
+    assert_eq!(block_on(cacher.value(5)), Ok(10));
+    assert_eq!(calls.get(), 1);
+
+    // 出错的结果不会被缓存
+    assert_eq!(block_on(cacher.value(0)), Err("zero".to_string()));
+    assert!(!cacher.is_cached(&0));
+    assert_eq!(block_on(cacher.value(0)), Err("zero".to_string()));
+    assert_eq!(calls.get(), 3);
+
+    assert!(cacher.invalidate(&5));
+    assert_eq!(block_on(cacher.value(5)), Ok(10));
+    assert_eq!(calls.get(), 4);
+}
+
 // 闭包捕获变量有三种途径，恰好对应函数参数的三种传入方式：
 // 转移所有权、可变借用、不可变借用，因此相应的 Fn 特征也有三种：
 fn fn_once<F>(func: F)
@@ -243,4 +1476,220 @@ fn factory(x: i32) -> Box<dyn Fn(i32) -> i32> {
     }else {
         Box::new(move |x| x - num)
     }
-}
\ No newline at end of file
+}
+
+// 闭包组合子
+// 每个组合子都尽量保留最强的闭包特征：输入是 Fn，返回的也是 Fn；
+// 需要修改状态的组合子把状态放进 Cell/RefCell，这样依然可以返回 Fn。
+// compose/and_then/partial 另外提供 _mut、_once 版本，接收 FnMut、FnOnce
+
+// compose(f, g)(x) == f(g(x))
+fn compose<A, B, C>(f: impl Fn(B) -> C, g: impl Fn(A) -> B) -> impl Fn(A) -> C {
+    move |x| f(g(x))
+}
+fn compose_mut<A, B, C>(mut f: impl FnMut(B) -> C, mut g: impl FnMut(A) -> B) -> impl FnMut(A) -> C {
+    move |x| f(g(x))
+}
+fn compose_once<A, B, C>(f: impl FnOnce(B) -> C, g: impl FnOnce(A) -> B) -> impl FnOnce(A) -> C {
+    move |x| f(g(x))
+}
+
+// and_then(f, g)(x) == g(f(x))，即先执行 f 再执行 g
+fn and_then<A, B, C>(f: impl Fn(A) -> B, g: impl Fn(B) -> C) -> impl Fn(A) -> C {
+    compose(g, f)
+}
+fn and_then_mut<A, B, C>(f: impl FnMut(A) -> B, g: impl FnMut(B) -> C) -> impl FnMut(A) -> C {
+    compose_mut(g, f)
+}
+fn and_then_once<A, B, C>(f: impl FnOnce(A) -> B, g: impl FnOnce(B) -> C) -> impl FnOnce(A) -> C {
+    compose_once(g, f)
+}
+
+// 固定第一个参数
+fn partial<A: Clone, B, C>(f: impl Fn(A, B) -> C, a: A) -> impl Fn(B) -> C {
+    move |b| f(a.clone(), b)
+}
+fn partial_mut<A: Clone, B, C>(mut f: impl FnMut(A, B) -> C, a: A) -> impl FnMut(B) -> C {
+    move |b| f(a.clone(), b)
+}
+fn partial_once<A, B, C>(f: impl FnOnce(A, B) -> C, a: A) -> impl FnOnce(B) -> C {
+    move |b| f(a, b)
+}
+
+// 柯里化：f(a, b) 变成 f(a)(b)
+fn curry<A, B, C, F>(f: F) -> impl Fn(A) -> Box<dyn Fn(B) -> C>
+where
+    A: Clone + 'static,
+    F: Fn(A, B) -> C + 'static,
+{
+    let f = Rc::new(f);
+    move |a| {
+        let f = Rc::clone(&f);
+        Box::new(move |b| f(a.clone(), b))
+    }
+}
+
+// 记住每个参数的结果，结果放在 RefCell 里，返回的依然是 Fn
+fn memoize<A, B>(f: impl Fn(A) -> B) -> impl Fn(A) -> B
+where
+    A: Hash + Eq + Clone,
+    B: Clone,
+{
+    let cache = RefCell::new(HashMap::new());
+    move |a: A| {
+        if let Some(b) = cache.borrow().get(&a) {
+            return B::clone(b);
+        }
+        // 调用 f 时不持有借用，f 递归调用自身也不会 panic
+        let b = f(a.clone());
+        cache.borrow_mut().insert(a, b.clone());
+        b
+    }
+}
+
+// 只能成功调用一次
+#[derive(Debug, PartialEq)]
+struct AlreadyCalled;
+fn once<A, B>(f: impl FnOnce(A) -> B) -> impl Fn(A) -> Result<B, AlreadyCalled> {
+    let f = Cell::new(Some(f));
+    move |a| f.take().map(|f| f(a)).ok_or(AlreadyCalled)
+}
+
+// 节流和防抖的窗口，可以按调用次数或者时间计算
+#[derive(Debug, Clone, Copy)]
+enum Window {
+    Calls(usize),
+    Time(Duration),
+}
+
+// 节流：窗口内只执行第一次调用，其余调用被忽略，返回 None
+fn throttle<A, B>(f: impl Fn(A) -> B, window: Window) -> impl Fn(A) -> Option<B> {
+    let calls = Cell::new(0usize);
+    let last_run: Cell<Option<Instant>> = Cell::new(None);
+    move |a| {
+        let run = match window {
+            Window::Calls(n) => {
+                let c = calls.get();
+                calls.set(c + 1);
+                c % n.max(1) == 0
+            }
+            Window::Time(d) => last_run.get().is_none_or(|t| t.elapsed() >= d),
+        };
+        if run {
+            last_run.set(Some(Instant::now()));
+            Some(f(a))
+        } else {
+            None
+        }
+    }
+}
+
+// 防抖：连续调用视为一串，
+// 按次数时每攒够 n 次调用才执行最后一次；
+// 按时间时只有距离上一次调用足够久才执行
+fn debounce<A, B>(f: impl Fn(A) -> B, window: Window) -> impl Fn(A) -> Option<B> {
+    let calls = Cell::new(0usize);
+    let last_call: Cell<Option<Instant>> = Cell::new(None);
+    move |a| {
+        let run = match window {
+            Window::Calls(n) => {
+                let c = calls.get() + 1;
+                calls.set(c % n.max(1));
+                c >= n.max(1)
+            }
+            Window::Time(d) => {
+                let quiet = last_call.get().is_none_or(|t| t.elapsed() >= d);
+                last_call.set(Some(Instant::now()));
+                quiet
+            }
+        };
+        run.then(|| f(a))
+    }
+}
+
+// 失败后重试，最多调用 attempts 次，返回最后一次的错误
+fn retry<A: Clone, B, E>(f: impl Fn(A) -> Result<B, E>, attempts: usize) -> impl Fn(A) -> Result<B, E> {
+    retry_with_delay(f, attempts, Duration::ZERO)
+}
+fn retry_with_delay<A: Clone, B, E>(
+    f: impl Fn(A) -> Result<B, E>,
+    attempts: usize,
+    delay: Duration,
+) -> impl Fn(A) -> Result<B, E> {
+    move |a| {
+        let mut result = f(a.clone());
+        for _ in 1..attempts {
+            if result.is_ok() {
+                break;
+            }
+            thread::sleep(delay);
+            result = f(a.clone());
+        }
+        result
+    }
+}
+
+fn combinator_test() {
+    let add_one = |x: i32| x + 1;
+    let double = |x: i32| x * 2;
+    assert_eq!(compose(add_one, double)(5), 11);
+    assert_eq!(and_then(add_one, double)(5), 12);
+    // factory 返回的 Box<dyn Fn> 同样可以组合
+    assert_eq!(compose(factory(3), factory(0))(1), 1);
+
+    let mut log = Vec::new();
+    let mut f = and_then_mut(|x: i32| { log.push(x); x }, double);
+    f(1);
+    f(2);
+    drop(f);
+    assert_eq!(log, vec![1, 2]);
+
+    let s = String::from("hello");
+    let f = compose_once(move |n: usize| s.len() + n, |x: usize| x * 2);
+    assert_eq!(f(1), 7);
+
+    let add = |a: i32, b: i32| a + b;
+    assert_eq!(partial(add, 10)(5), 15);
+    assert_eq!(curry(add)(1)(2), 3);
+    let mut sum = 0;
+    partial_mut(|a: i32, b: i32| sum += a * b, 3)(4);
+    assert_eq!(sum, 12);
+    let name = String::from("rust");
+    assert_eq!(partial_once(|a: String, b: &str| a + b, name)("acean"), "rustacean");
+
+    let calls = Cell::new(0);
+    let square = memoize(|x: u64| { calls.set(calls.get() + 1); x * x });
+    assert_eq!((square(3), square(3), square(4)), (9, 9, 16));
+    assert_eq!(calls.get(), 2);
+
+    let v = vec![1, 2, 3];
+    let consume = once(move |n: usize| v.into_iter().take(n).sum::<i32>());
+    assert_eq!(consume(2), Ok(3));
+    assert_eq!(consume(2), Err(AlreadyCalled));
+
+    let t = throttle(double, Window::Calls(3));
+    let r: Vec<_> = (1..=5).map(&t).collect();
+    assert_eq!(r, vec![Some(2), None, None, Some(8), None]);
+    let t = throttle(double, Window::Time(Duration::from_millis(30)));
+    assert_eq!((t(1), t(2)), (Some(2), None));
+    thread::sleep(Duration::from_millis(40));
+    assert_eq!(t(3), Some(6));
+
+    let d = debounce(double, Window::Calls(2));
+    let r: Vec<_> = (1..=4).map(&d).collect();
+    assert_eq!(r, vec![None, Some(4), None, Some(8)]);
+    let d = debounce(double, Window::Time(Duration::from_millis(30)));
+    assert_eq!((d(1), d(2)), (Some(2), None));
+    thread::sleep(Duration::from_millis(40));
+    assert_eq!(d(3), Some(6));
+
+    let attempts = Cell::new(0);
+    let flaky = |x: i32| {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() < 3 { Err("busy") } else { Ok(x) }
+    };
+    assert_eq!(retry(flaky, 2)(7), Err("busy"));
+    attempts.set(0);
+    assert_eq!(retry_with_delay(flaky, 3, Duration::from_millis(1))(7), Ok(7));
+    assert_eq!(attempts.get(), 3);
+}
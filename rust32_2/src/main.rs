@@ -1,6 +1,13 @@
 #![allow(warnings,unused)]
 
-use std::collections::HashMap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::ops::RangeBounds;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::iter::FusedIterator;
 // 迭代器
 // IntoIterator::into_iter 是使用完全限定的方式去调用 into_iter 方法，
 // 跟 values.into_iter() 是等价的。
@@ -83,8 +90,15 @@ fn main() {
     .fold(0, |sum, acm| sum + acm);
     println!("{}", val);
 
+    counter_test();
+    shoe_inventory_test();
+    pipeline_test();
+    iter_ext_test();
+    roster_test();
+
 
 }
+#[derive(Debug, Clone, PartialEq)]
 struct Shoe{
     size: u32,
     style: String,
@@ -93,22 +107,1340 @@ fn shoes_in_size(shoes: Vec<Shoe>, shoe_size: u32) -> Vec<Shoe>{
     shoes.into_iter().filter(|s| s.size == shoe_size).collect()
 }
 
+// 鞋子目录
+// shoes_in_size 会拿走整个 Vec 的所有权，ShoeInventory 则按尺码和款式建立索引，
+// 查询结果都是对目录中鞋子的借用，目录本身可以反复查询
+#[derive(Debug, Default)]
+struct ShoeInventory {
+    shoes: Vec<Shoe>,
+    // 尺码 -> 下标，BTreeMap 支持按尺码区间查询
+    by_size: BTreeMap<u32, Vec<usize>>,
+    // 款式 -> 下标
+    by_style: HashMap<String, Vec<usize>>,
+}
+impl ShoeInventory {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn add(&mut self, shoe: Shoe) {
+        let idx = self.shoes.len();
+        self.by_size.entry(shoe.size).or_default().push(idx);
+        self.by_style.entry(shoe.style.clone()).or_default().push(idx);
+        self.shoes.push(shoe);
+    }
+
+    fn len(&self) -> usize {
+        self.shoes.len()
+    }
+
+    fn iter(&self) -> std::slice::Iter<'_, Shoe> {
+        self.shoes.iter()
+    }
+
+    fn in_size(&self, size: u32) -> Vec<&Shoe> {
+        self.query().size(size).run()
+    }
+
+    fn in_style(&self, style: &str) -> Vec<&Shoe> {
+        self.query().style(style).run()
+    }
+
+    fn in_size_range(&self, sizes: impl RangeBounds<u32>) -> Vec<&Shoe> {
+        self.query().size_range(sizes).run()
+    }
+
+    // 按款式分组，款式按字典序排列
+    fn group_by_style(&self) -> BTreeMap<&str, Vec<&Shoe>> {
+        self.by_style
+            .iter()
+            .map(|(style, idx)| (style.as_str(), idx.iter().map(|&i| &self.shoes[i]).collect()))
+            .collect()
+    }
+
+    fn query(&self) -> ShoeQuery<'_> {
+        ShoeQuery { inventory: self, candidates: None, filters: Vec::new(), order: None }
+    }
+}
+impl FromIterator<Shoe> for ShoeInventory {
+    fn from_iter<I: IntoIterator<Item = Shoe>>(iter: I) -> Self {
+        let mut inventory = ShoeInventory::new();
+        for shoe in iter {
+            inventory.add(shoe);
+        }
+        inventory
+    }
+}
+
+// 多条件查询
+// size/size_range/style 走索引缩小候选集合，filter 接收任意闭包，
+// 所有条件之间是"并且"的关系
+struct ShoeQuery<'a> {
+    inventory: &'a ShoeInventory,
+    // None 表示还没有用索引缩小过，候选是全部鞋子
+    candidates: Option<BTreeSet<usize>>,
+    filters: Vec<Box<dyn Fn(&Shoe) -> bool + 'a>>,
+    order: Option<Box<dyn Fn(&Shoe, &Shoe) -> Ordering + 'a>>,
+}
+impl<'a> ShoeQuery<'a> {
+    // 与当前候选集合求交集
+    fn narrow(mut self, idx: impl Iterator<Item = usize>) -> Self {
+        let idx: BTreeSet<usize> = idx.collect();
+        self.candidates = Some(match self.candidates {
+            Some(c) => c.intersection(&idx).copied().collect(),
+            None => idx,
+        });
+        self
+    }
+
+    fn size(self, size: u32) -> Self {
+        self.size_range(size..=size)
+    }
+
+    fn size_range(self, sizes: impl RangeBounds<u32>) -> Self {
+        let inventory = self.inventory;
+        self.narrow(inventory.by_size.range(sizes).flat_map(|(_, idx)| idx.iter().copied()))
+    }
+
+    fn style(self, style: &str) -> Self {
+        let inventory = self.inventory;
+        let idx = inventory.by_style.get(style).into_iter().flatten().copied();
+        self.narrow(idx)
+    }
+
+    fn filter(mut self, f: impl Fn(&Shoe) -> bool + 'a) -> Self {
+        self.filters.push(Box::new(f));
+        self
+    }
+
+    fn sort_by(mut self, cmp: impl Fn(&Shoe, &Shoe) -> Ordering + 'a) -> Self {
+        self.order = Some(Box::new(cmp));
+        self
+    }
+
+    fn sort_by_key<K: Ord>(self, key: impl Fn(&Shoe) -> K + 'a) -> Self {
+        self.sort_by(move |a, b| key(a).cmp(&key(b)))
+    }
+
+    fn run(self) -> Vec<&'a Shoe> {
+        let shoes = &self.inventory.shoes;
+        let candidates: Box<dyn Iterator<Item = &'a Shoe>> = match &self.candidates {
+            Some(idx) => Box::new(idx.iter().map(move |&i| &shoes[i])),
+            None => Box::new(shoes.iter()),
+        };
+        let mut result: Vec<&Shoe> = candidates.filter(|s| self.filters.iter().all(|f| f(s))).collect();
+        if let Some(cmp) = &self.order {
+            // 稳定排序，相等的鞋子保持入库顺序
+            result.sort_by(|a, b| cmp(a, b));
+        }
+        result
+    }
+
+    fn count(self) -> usize {
+        self.run().len()
+    }
+}
+
+fn shoe_inventory_test() {
+    let shoe = |size, style: &str| Shoe { size, style: style.to_string() };
+    let inventory: ShoeInventory = vec![
+        shoe(42, "sneaker"),
+        shoe(39, "boot"),
+        shoe(43, "sandal"),
+        shoe(40, "sneaker"),
+        shoe(42, "boot"),
+        shoe(45, "sneaker"),
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(inventory.in_size(42), vec![&shoe(42, "sneaker"), &shoe(42, "boot")]);
+    assert_eq!(inventory.in_style("boot").len(), 2);
+    assert_eq!(inventory.in_style("loafer").len(), 0);
+    assert_eq!(inventory.in_size_range(40..=43).len(), 4);
+
+    let found = inventory
+        .query()
+        .size_range(40..=45)
+        .style("sneaker")
+        .filter(|s| s.size % 2 == 0)
+        .sort_by_key(|s| Reverse(s.size))
+        .run();
+    assert_eq!(found, vec![&shoe(42, "sneaker"), &shoe(40, "sneaker")]);
+    assert_eq!(inventory.query().filter(|s| s.style.starts_with('s')).count(), 4);
+
+    let groups = inventory.group_by_style();
+    assert_eq!(groups.keys().copied().collect::<Vec<_>>(), vec!["boot", "sandal", "sneaker"]);
+    assert_eq!(groups["sneaker"].len(), 3);
+
+    // 查询只是借用，目录依然可用
+    assert_eq!(inventory.len(), 6);
+    let sizes: Vec<u32> = inventory.query().sort_by(|a, b| a.size.cmp(&b.size)).run().iter().map(|s| s.size).collect();
+    assert_eq!(sizes, vec![39, 40, 42, 42, 43, 45]);
+}
+
 // 实现Iterator特征
-struct Counter { count: u32 }
-impl Counter {
+// Counter<T> 可以用于任意整数类型，起点、终点（包含或不包含）和步长（可以为负）都可配置。
+// 内部按下标计算第 i 个元素 start + i * step，中间结果用 i128 计算，
+// 不会像逐个累加那样在类型边界处溢出。不指定终点时一直数到类型的最大（或最小）值。
+// 支持 128 位以下的整数类型
+trait Integer: Copy + Ord {
+    const MIN: Self;
+    const MAX: Self;
+    fn to_i128(self) -> i128;
+    fn from_i128(v: i128) -> Option<Self>;
+}
+macro_rules! impl_integer {
+    ($($t:ty),*) => {$(
+        impl Integer for $t {
+            const MIN: Self = <$t>::MIN;
+            const MAX: Self = <$t>::MAX;
+            fn to_i128(self) -> i128 {
+                self as i128
+            }
+            fn from_i128(v: i128) -> Option<Self> {
+                <$t>::try_from(v).ok()
+            }
+        }
+    )*};
+}
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+#[derive(Debug, Clone)]
+struct Counter<T: Integer> {
+    start: T,
+    end: T,
+    inclusive: bool,
+    // 没有指定终点
+    open: bool,
+    step: i128,
+    // 剩余元素的下标区间 [front, back)
+    front: u128,
+    back: u128,
+}
+impl Counter<u32> {
+    // 保留原来的行为：从 1 数到 5
     fn new() -> Self {
-        Self { count: 0 }
+        Counter::range_inclusive(1, 5)
     }
 }
-impl Iterator for Counter {
-    type Item = u32;
+impl<T: Integer> Counter<T> {
+    // [start, end)
+    fn range(start: T, end: T) -> Self {
+        Self::build(start, end, false, false, 1)
+    }
+
+    // [start, end]
+    fn range_inclusive(start: T, end: T) -> Self {
+        Self::build(start, end, true, false, 1)
+    }
+
+    // 从 start 开始一直数到类型的最大值，配合 with_step 传入负数时数到最小值
+    fn starting_at(start: T) -> Self {
+        Self::build(start, T::MAX, true, true, 1)
+    }
+
+    // 设置步长，会重置迭代进度。步长为 0 时 panic，与 Iterator::step_by 一致
+    fn with_step(self, step: i128) -> Self {
+        assert!(step != 0, "step must be non-zero");
+        // 不指定终点的计数器，负步长时终点改为最小值
+        let end = match (self.open, step > 0) {
+            (true, true) => T::MAX,
+            (true, false) => T::MIN,
+            (false, _) => self.end,
+        };
+        Self::build(self.start, end, self.inclusive, self.open, step)
+    }
+
+    fn build(start: T, end: T, inclusive: bool, open: bool, step: i128) -> Self {
+        let (s, e) = (start.to_i128(), end.to_i128());
+        // 沿着步长方向的距离
+        let distance = if step > 0 { e - s } else { s - e };
+        let stride = step.unsigned_abs();
+        let len = match (distance, inclusive) {
+            (d, true) if d >= 0 => d as u128 / stride + 1,
+            (d, false) if d > 0 => (d as u128).div_ceil(stride),
+            _ => 0,
+        };
+        Self { start, end, inclusive, open, step, front: 0, back: len }
+    }
+
+    fn nth_value(&self, i: u128) -> T {
+        // i 在 [0, len) 内时结果一定落在 [start, end] 之间，不会溢出
+        let v = self.start.to_i128() + self.step * i as i128;
+        T::from_i128(v).expect("counter value out of range")
+    }
+
+    fn remaining(&self) -> u128 {
+        self.back - self.front
+    }
+}
+impl<T: Integer> Iterator for Counter<T> {
+    type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.count < 5 {
-            self.count += 1;
-            Some(self.count)
+        if self.front < self.back {
+            let v = self.nth_value(self.front);
+            self.front += 1;
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    // 超过 usize 的长度按 usize::MAX 报告
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.remaining()) {
+            Ok(n) => (n, Some(n)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.front = self.front.saturating_add(n as u128).min(self.back);
+        self.next()
+    }
+}
+impl<T: Integer> DoubleEndedIterator for Counter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front < self.back {
+            self.back -= 1;
+            Some(self.nth_value(self.back))
         } else {
             None
         }
     }
-}
\ No newline at end of file
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        self.back = self.back.saturating_sub(n as u128).max(self.front);
+        self.next_back()
+    }
+}
+// 和标准库的 Range 一样，只给整个取值范围的元素个数都放得进 usize 的类型实现，
+// 64 位整数的计数器长度可能超过 usize，只能通过 size_hint 得到上限
+macro_rules! impl_exact_size {
+    ($($t:ty),*) => {$(
+        impl ExactSizeIterator for Counter<$t> {}
+    )*};
+}
+impl_exact_size!(i8, i16, u8, u16);
+#[cfg(target_pointer_width = "64")]
+impl_exact_size!(i32, u32);
+impl<T: Integer> FusedIterator for Counter<T> {}
+
+fn counter_test() {
+    assert_eq!(Counter::range(0, 5).collect::<Vec<i32>>(), vec![0, 1, 2, 3, 4]);
+    assert_eq!(Counter::range_inclusive(0u8, 10).with_step(3).collect::<Vec<_>>(), vec![0, 3, 6, 9]);
+    assert_eq!(Counter::range(10i64, 0).with_step(-4).collect::<Vec<_>>(), vec![10, 6, 2]);
+    assert_eq!(Counter::range(5, 5).count(), 0);
+    assert_eq!(Counter::range(5, 1).count(), 0);
+
+    // 在类型边界处停下而不是溢出
+    assert_eq!(Counter::starting_at(250u8).collect::<Vec<_>>(), vec![250, 251, 252, 253, 254, 255]);
+    assert_eq!(Counter::starting_at(-126i8).with_step(-1).collect::<Vec<_>>(), vec![-126, -127, -128]);
+    assert_eq!(Counter::range_inclusive(3u8, 255).with_step(-1).count(), 0);
+    assert_eq!(Counter::range_inclusive(0u8, 255).with_step(100).collect::<Vec<_>>(), vec![0, 100, 200]);
+
+    // rev、len、zip
+    let c = Counter::range_inclusive(1u16, 5);
+    assert_eq!(c.len(), 5);
+    assert_eq!(c.clone().rev().collect::<Vec<_>>(), vec![5, 4, 3, 2, 1]);
+    let mut c = Counter::range(0u16, 10).with_step(2);
+    assert_eq!((c.next(), c.next_back(), c.len()), (Some(0), Some(8), 3));
+    assert_eq!(c.nth(1), Some(4));
+    assert_eq!(c.nth_back(5), None);
+    assert_eq!((c.next(), c.next()), (None, None));
+    let pairs: Vec<_> = Counter::range(0u16, 3).zip(Counter::range(0u16, 3).rev()).collect();
+    assert_eq!(pairs, vec![(0, 2), (1, 1), (2, 0)]);
+    // 64 位整数没有 len，超过 usize 的长度只给出下限
+    assert_eq!(Counter::starting_at(0u64).size_hint(), (usize::MAX, None));
+    assert_eq!(Counter::range(0u64, 10).size_hint(), (10, Some(10)));
+    #[cfg(target_pointer_width = "64")]
+    assert_eq!(Counter::range_inclusive(i32::MIN, i32::MAX).len(), 1 << 32);
+}
+
+
+// 并行流水线
+// 和迭代器适配器一样，map/filter 只是把处理步骤组合起来，直到 collect/fold 才真正执行。
+// 执行时从源迭代器里按需取出固定大小的块，多个线程抢块处理，结果按块的顺序交还给调用方，
+// 所以 collect 保持原有顺序。已取出但还没交还的块最多 in_flight 个，
+// 某个块处理得慢时其他线程会停下来等它，内存占用不随输入增长。
+// sequential 模式走同样的取块逻辑，只是在当前线程逐块处理，
+// 只要 fold 的 combine 满足结合律，两种模式的结果完全一致
+type Stage<S, T> = Arc<dyn Fn(S) -> Option<T> + Send + Sync>;
+
+struct Pipeline<S, T> {
+    source: Box<dyn Iterator<Item = S> + Send>,
+    stage: Stage<S, T>,
+    workers: usize,
+    chunk_size: usize,
+    in_flight: usize,
+}
+impl<S: Send + 'static> Pipeline<S, S> {
+    fn new<I>(items: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        I::IntoIter: Send + 'static,
+    {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        Self { source: Box::new(items.into_iter()), stage: Arc::new(Some), workers, chunk_size: 1024, in_flight: workers * 2 }
+    }
+}
+
+// 让迭代器链直接接上流水线，不需要先 collect 成 Vec
+trait IntoPipeline: Iterator + Sized {
+    fn into_pipeline(self) -> Pipeline<Self::Item, Self::Item>;
+}
+impl<I> IntoPipeline for I
+where
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+{
+    fn into_pipeline(self) -> Pipeline<I::Item, I::Item> {
+        Pipeline::new(self)
+    }
+}
+
+// 工作线程共享的取块状态
+struct Feed<S> {
+    source: Box<dyn Iterator<Item = S> + Send>,
+    // 下一个块的编号
+    next: usize,
+    // 已取出但还没交还给调用方的块数
+    in_flight: usize,
+    // 有线程 panic 了，其余线程不再取块
+    aborted: bool,
+}
+
+struct Gate<S> {
+    feed: Mutex<Feed<S>>,
+    ready: Condvar,
+    limit: usize,
+}
+impl<S> Gate<S> {
+    fn lock(&self) -> MutexGuard<'_, Feed<S>> {
+        self.feed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 取下一个块，在途的块太多时等待
+    fn pull(&self, chunk_size: usize) -> Option<(usize, Vec<S>)> {
+        let mut feed = self.lock();
+        while feed.in_flight >= self.limit && !feed.aborted {
+            feed = self.ready.wait(feed).unwrap_or_else(|e| e.into_inner());
+        }
+        if feed.aborted {
+            return None;
+        }
+        let chunk: Vec<S> = feed.source.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            return None;
+        }
+        let i = feed.next;
+        feed.next += 1;
+        feed.in_flight += 1;
+        Some((i, chunk))
+    }
+
+    // 一个块的结果已经交还给调用方
+    fn release(&self) {
+        self.lock().in_flight -= 1;
+        self.ready.notify_all();
+    }
+}
+
+// 工作线程 panic 时唤醒其他等待的线程，避免它们一直等下去
+struct AbortOnPanic<'a, S>(&'a Gate<S>);
+impl<S> Drop for AbortOnPanic<'_, S> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.lock().aborted = true;
+            self.0.ready.notify_all();
+        }
+    }
+}
+
+impl<S: Send + 'static, T: Send + 'static> Pipeline<S, T> {
+    fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    // 同时在途的块数上限
+    fn in_flight(mut self, in_flight: usize) -> Self {
+        self.in_flight = in_flight.max(1);
+        self
+    }
+
+    // 在当前线程执行
+    fn sequential(self) -> Self {
+        self.workers(1)
+    }
+
+    fn then<U>(self, next: impl Fn(T) -> Option<U> + Send + Sync + 'static) -> Pipeline<S, U> {
+        let stage = self.stage;
+        Pipeline {
+            source: self.source,
+            stage: Arc::new(move |s| stage(s).and_then(&next)),
+            workers: self.workers,
+            chunk_size: self.chunk_size,
+            in_flight: self.in_flight,
+        }
+    }
+
+    fn map<U>(self, f: impl Fn(T) -> U + Send + Sync + 'static) -> Pipeline<S, U> {
+        self.then(move |t| Some(f(t)))
+    }
+
+    fn filter(self, f: impl Fn(&T) -> bool + Send + Sync + 'static) -> Pipeline<S, T> {
+        self.then(move |t| f(&t).then_some(t))
+    }
+
+    fn filter_map<U>(self, f: impl Fn(T) -> Option<U> + Send + Sync + 'static) -> Pipeline<S, U> {
+        self.then(f)
+    }
+
+    // 对每个块执行 per_chunk，结果按块的顺序交给 sink，sink 在当前线程调用
+    fn execute<R: Send>(
+        self,
+        per_chunk: impl Fn(&mut dyn Iterator<Item = T>) -> R + Sync,
+        mut sink: impl FnMut(R),
+    ) {
+        let gate = Gate {
+            feed: Mutex::new(Feed { source: self.source, next: 0, in_flight: 0, aborted: false }),
+            ready: Condvar::new(),
+            limit: if self.workers == 1 { 1 } else { self.in_flight },
+        };
+        let stage = &self.stage;
+        let run = |chunk: Vec<S>| per_chunk(&mut chunk.into_iter().filter_map(|s| stage(s)));
+
+        if self.workers == 1 {
+            while let Some((_, chunk)) = gate.pull(self.chunk_size) {
+                sink(run(chunk));
+                gate.release();
+            }
+            return;
+        }
+        let chunk_size = self.chunk_size;
+        let (gate, run) = (&gate, &run);
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            for _ in 0..self.workers {
+                let tx = tx.clone();
+                scope.spawn(move || {
+                    let _abort = AbortOnPanic(gate);
+                    while let Some((i, chunk)) = gate.pull(chunk_size) {
+                        if tx.send((i, run(chunk))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+            // 先到的块暂存起来，等前面的块到齐后按顺序交出
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (i, r) in rx {
+                pending.insert(i, r);
+                while let Some(r) = pending.remove(&next) {
+                    sink(r);
+                    next += 1;
+                    gate.release();
+                }
+            }
+        });
+    }
+
+    fn collect(self) -> Vec<T> {
+        let mut out = Vec::new();
+        self.execute(|it| it.collect::<Vec<_>>(), |chunk| out.extend(chunk));
+        out
+    }
+
+    // 每个块从 init 开始用 f 累积，块之间用 combine 按顺序合并
+    fn fold<A>(
+        self,
+        init: A,
+        f: impl Fn(A, T) -> A + Sync,
+        combine: impl Fn(A, A) -> A,
+    ) -> A
+    where
+        A: Clone + Send + Sync,
+    {
+        let mut acc: Option<A> = None;
+        self.execute(|it| it.fold(init.clone(), &f), |part| {
+            acc = Some(match acc.take() {
+                Some(a) => combine(a, part),
+                None => part,
+            });
+        });
+        acc.unwrap_or(init)
+    }
+
+    fn count(self) -> usize {
+        self.fold(0, |n, _| n + 1, |a, b| a + b)
+    }
+}
+impl<S: Send + 'static, T: Send + std::iter::Sum + 'static> Pipeline<S, T> {
+    fn sum(self) -> T {
+        let mut acc: Option<T> = None;
+        self.execute(|it| it.sum::<T>(), |part| {
+            acc = Some(match acc.take() {
+                Some(a) => [a, part].into_iter().sum(),
+                None => part,
+            });
+        });
+        acc.unwrap_or_else(|| std::iter::empty().sum())
+    }
+}
+
+fn pipeline_test() {
+    // 与开头 Counter 的 zip/map/filter/sum 例子结果相同
+    let sum: u32 = Counter::new()
+        .zip(Counter::new().skip(1))
+        .into_pipeline()
+        .workers(3)
+        .chunk_size(1)
+        .map(|(a, b)| a * b)
+        .filter(|x| x % 3 == 0)
+        .sum();
+    assert_eq!(18, sum);
+
+    let data: Vec<u64> = (1..=10_000).collect();
+    let parallel = Pipeline::new(data.clone()).workers(4).chunk_size(97).map(|x| x * x).filter(|x| x % 7 == 1);
+    let sequential = Pipeline::new(data.clone()).sequential().chunk_size(97).map(|x| x * x).filter(|x| x % 7 == 1);
+    let expected: Vec<u64> = data.iter().map(|x| x * x).filter(|x| x % 7 == 1).collect();
+    assert_eq!(parallel.collect(), expected);
+    assert_eq!(sequential.collect(), expected);
+
+    // 字符串拼接不满足交换律，顺序依然正确
+    let joined = Pipeline::new(0..20)
+        .workers(4)
+        .chunk_size(3)
+        .map(|x: i32| x.to_string())
+        .fold(String::new(), |acc, s| acc + &s, |a, b| a + &b);
+    assert_eq!(joined, (0..20).map(|x| x.to_string()).collect::<String>());
+
+    assert_eq!(Pipeline::new(Vec::<i32>::new()).fold(0, |a, x| a + x, |a, b| a + b), 0);
+    assert_eq!(Pipeline::new(vec!["a", "bb", "ccc"]).filter_map(|s| (s.len() > 1).then_some(s.len())).count(), 2);
+    assert_eq!(Pipeline::new(Vec::<u64>::new()).workers(3).sum(), 0);
+
+    // 按需从源头取数据：取出但还没处理完的元素不超过 in_flight 个块
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let processed = Arc::new(AtomicUsize::new(0));
+    let max_ahead = Arc::new(AtomicUsize::new(0));
+    let (p, q, m) = (Arc::clone(&pulled), Arc::clone(&processed), Arc::clone(&max_ahead));
+    let source = (0..100_000u64).inspect(move |_| {
+        p.fetch_add(1, AtomicOrdering::SeqCst);
+    });
+    let total = source
+        .into_pipeline()
+        .workers(4)
+        .chunk_size(10)
+        .in_flight(4)
+        .map(move |x| {
+            // 先读 pulled 再读 processed，估计值只会偏小，不会因为竞争而偏大
+            q.fetch_add(1, AtomicOrdering::SeqCst);
+            let seen = pulled.load(AtomicOrdering::SeqCst);
+            let ahead = seen.saturating_sub(q.load(AtomicOrdering::SeqCst));
+            m.fetch_max(ahead, AtomicOrdering::SeqCst);
+            x
+        })
+        .sum();
+    assert_eq!(total, (0..100_000u64).sum::<u64>());
+    assert_eq!(processed.load(AtomicOrdering::SeqCst), 100_000);
+    assert!(max_ahead.load(AtomicOrdering::SeqCst) <= 4 * 10);
+}
+
+// 自定义迭代器适配器
+// 和 Counter 一样，每个适配器都是一个具名的结构体，惰性求值，
+// 并且尽量给出准确的 size_hint。通过扩展特征 IterExt 挂到所有迭代器上
+trait IterExt: Iterator + Sized {
+    // 长度为 n 的滑动窗口，[1,2,3] 的 2 窗口是 [1,2]、[2,3]
+    fn sliding_windows(self, n: usize) -> SlidingWindows<Self>
+    where
+        Self::Item: Clone,
+    {
+        assert!(n != 0, "window size must be non-zero");
+        SlidingWindows { iter: self, n, buf: VecDeque::with_capacity(n) }
+    }
+
+    // 把 key 相同的相邻元素分为一组
+    fn chunk_by<K, F>(self, key: F) -> ChunkBy<Self, F>
+    where
+        K: PartialEq,
+        F: FnMut(&Self::Item) -> K,
+    {
+        ChunkBy { iter: self, key, pending: None }
+    }
+
+    // 交替取两个迭代器的元素，一个取完后继续取另一个
+    fn interleave<J>(self, other: J) -> Interleave<Self, J::IntoIter>
+    where
+        J: IntoIterator<Item = Self::Item>,
+    {
+        Interleave { a: self, b: other.into_iter(), flag: false }
+    }
+
+    // 去掉 key 与前一个元素相同的相邻元素
+    fn dedup_by_key<K, F>(self, key: F) -> DedupByKey<Self, F, K>
+    where
+        K: PartialEq,
+        F: FnMut(&Self::Item) -> K,
+    {
+        DedupByKey { iter: self, key, last: None }
+    }
+
+    // 每 n 个元素打包成一个 Vec，最后一批可能不足 n 个
+    fn batch(self, n: usize) -> Batch<Self> {
+        assert!(n != 0, "batch size must be non-zero");
+        Batch { iter: self, n }
+    }
+
+    // 先返回第一个元素，之后跳过元素直到 pred(上一个返回的元素, 当前元素) 为真
+    fn step_by_pred<P>(self, pred: P) -> StepByPred<Self, P>
+    where
+        Self::Item: Clone,
+        P: FnMut(&Self::Item, &Self::Item) -> bool,
+    {
+        StepByPred { iter: self, pred, last: None }
+    }
+
+    // 依次返回累积状态 f(state, item)，状态不再满足 pred 时结束
+    fn scan_while<St, F, P>(self, init: St, f: F, pred: P) -> ScanWhile<Self, St, F, P>
+    where
+        St: Clone,
+        F: FnMut(&St, Self::Item) -> St,
+        P: FnMut(&St) -> bool,
+    {
+        ScanWhile { iter: self, state: init, f, pred, done: false }
+    }
+
+    // 可以通过 peek_n 向前看多个元素的迭代器
+    fn peekable_n(self) -> PeekN<Self> {
+        PeekN { iter: self, buf: VecDeque::new() }
+    }
+}
+impl<I: Iterator> IterExt for I {}
+
+#[derive(Clone)]
+struct SlidingWindows<I: Iterator> {
+    iter: I,
+    n: usize,
+    // 上一个窗口的内容，还没返回过窗口时里面是已读到的元素
+    buf: VecDeque<I::Item>,
+}
+impl<I: Iterator> Iterator for SlidingWindows<I>
+where
+    I::Item: Clone,
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() == self.n {
+            self.buf.pop_front();
+        }
+        while self.buf.len() < self.n {
+            self.buf.push_back(self.iter.next()?);
+        }
+        Some(self.buf.iter().cloned().collect())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.iter.size_hint();
+        // 已经凑满一个窗口后，每读一个新元素就多一个窗口
+        let pending = if self.buf.len() == self.n { self.n - 1 } else { self.buf.len() };
+        // 还差 missing 个元素才能凑出下一个窗口，窗口数 = 剩余元素数 - missing，
+        // 不会比剩余元素数多，所以不会溢出，上界也总是精确的
+        let missing = self.n - 1 - pending;
+        let windows = |len: usize| len.saturating_sub(missing);
+        (windows(lo), hi.map(windows))
+    }
+}
+
+#[derive(Clone)]
+struct ChunkBy<I: Iterator, F> {
+    iter: I,
+    key: F,
+    // 已经读出来但属于下一组的元素
+    pending: Option<I::Item>,
+}
+impl<I, F, K> Iterator for ChunkBy<I, F>
+where
+    I: Iterator,
+    K: PartialEq,
+    F: FnMut(&I::Item) -> K,
+{
+    type Item = (K, Vec<I::Item>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.pending.take().or_else(|| self.iter.next())?;
+        let key = (self.key)(&first);
+        let mut group = vec![first];
+        for item in self.iter.by_ref() {
+            if (self.key)(&item) == key {
+                group.push(item);
+            } else {
+                self.pending = Some(item);
+                break;
+            }
+        }
+        Some((key, group))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.iter.size_hint();
+        let pending = self.pending.is_some() as usize;
+        // 至少一组（只要还有元素），最多每个元素一组
+        (lo.saturating_add(pending).min(1), hi.and_then(|h| h.checked_add(pending)))
+    }
+}
+
+#[derive(Clone)]
+struct Interleave<I, J> {
+    a: I,
+    b: J,
+    // 下一个该 b 了
+    flag: bool,
+}
+impl<I, J> Iterator for Interleave<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.flag = !self.flag;
+        if self.flag {
+            self.a.next().or_else(|| self.b.next())
+        } else {
+            self.b.next().or_else(|| self.a.next())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (alo, ahi) = self.a.size_hint();
+        let (blo, bhi) = self.b.size_hint();
+        let hi = match (ahi, bhi) {
+            (Some(a), Some(b)) => a.checked_add(b),
+            _ => None,
+        };
+        (alo.saturating_add(blo), hi)
+    }
+}
+
+#[derive(Clone)]
+struct DedupByKey<I, F, K> {
+    iter: I,
+    key: F,
+    last: Option<K>,
+}
+impl<I, F, K> Iterator for DedupByKey<I, F, K>
+where
+    I: Iterator,
+    K: PartialEq,
+    F: FnMut(&I::Item) -> K,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for item in self.iter.by_ref() {
+            let key = (self.key)(&item);
+            if self.last.as_ref() != Some(&key) {
+                self.last = Some(key);
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.iter.size_hint();
+        // 剩下的元素可能全都和上一个重复
+        let lo = if self.last.is_none() { lo.min(1) } else { 0 };
+        (lo, hi)
+    }
+}
+
+#[derive(Clone)]
+struct Batch<I> {
+    iter: I,
+    n: usize,
+}
+impl<I: Iterator> Iterator for Batch<I> {
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch: Vec<_> = self.iter.by_ref().take(self.n).collect();
+        (!batch.is_empty()).then_some(batch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.iter.size_hint();
+        (lo.div_ceil(self.n), hi.map(|h| h.div_ceil(self.n)))
+    }
+}
+
+#[derive(Clone)]
+struct StepByPred<I: Iterator, P> {
+    iter: I,
+    pred: P,
+    last: Option<I::Item>,
+}
+impl<I, P> Iterator for StepByPred<I, P>
+where
+    I: Iterator,
+    I::Item: Clone,
+    P: FnMut(&I::Item, &I::Item) -> bool,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = match self.last.take() {
+            None => self.iter.next()?,
+            Some(last) => {
+                let found = self.iter.by_ref().find(|item| (self.pred)(&last, item));
+                self.last = Some(last);
+                found?
+            }
+        };
+        self.last = Some(item.clone());
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.iter.size_hint();
+        let lo = if self.last.is_none() { lo.min(1) } else { 0 };
+        (lo, hi)
+    }
+}
+
+#[derive(Clone)]
+struct ScanWhile<I, St, F, P> {
+    iter: I,
+    state: St,
+    f: F,
+    pred: P,
+    done: bool,
+}
+impl<I, St, F, P> Iterator for ScanWhile<I, St, F, P>
+where
+    I: Iterator,
+    St: Clone,
+    F: FnMut(&St, I::Item) -> St,
+    P: FnMut(&St) -> bool,
+{
+    type Item = St;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let Some(item) = self.iter.next() else {
+            self.done = true;
+            return None;
+        };
+        let state = (self.f)(&self.state, item);
+        if !(self.pred)(&state) {
+            self.done = true;
+            return None;
+        }
+        self.state = state.clone();
+        Some(state)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, self.iter.size_hint().1)
+        }
+    }
+}
+impl<I, St, F, P> FusedIterator for ScanWhile<I, St, F, P>
+where
+    I: Iterator,
+    St: Clone,
+    F: FnMut(&St, I::Item) -> St,
+    P: FnMut(&St) -> bool,
+{
+}
+
+#[derive(Clone)]
+struct PeekN<I: Iterator> {
+    iter: I,
+    buf: VecDeque<I::Item>,
+}
+impl<I: Iterator> PeekN<I> {
+    // 查看接下来最多 n 个元素，不会消费它们
+    fn peek_n(&mut self, n: usize) -> &[I::Item] {
+        while self.buf.len() < n {
+            match self.iter.next() {
+                Some(item) => self.buf.push_back(item),
+                None => break,
+            }
+        }
+        let len = self.buf.len().min(n);
+        &self.buf.make_contiguous()[..len]
+    }
+}
+impl<I: Iterator> Iterator for PeekN<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buf.pop_front().or_else(|| self.iter.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.iter.size_hint();
+        let n = self.buf.len();
+        (lo.saturating_add(n), hi.and_then(|h| h.checked_add(n)))
+    }
+}
+
+// 每走一步都检查剩余元素个数落在 size_hint 给出的区间内
+fn check_size_hint<I: Iterator + Clone>(mut iter: I) {
+    loop {
+        let (lo, hi) = iter.size_hint();
+        let remaining = iter.clone().count();
+        assert!(lo <= remaining, "lower bound {} > {}", lo, remaining);
+        assert!(hi.is_none_or(|h| remaining <= h), "upper bound {:?} < {}", hi, remaining);
+        if iter.next().is_none() {
+            break;
+        }
+    }
+}
+
+fn iter_ext_test() {
+    let v = vec![1, 1, 2, 3, 3, 3, 4, 8, 9];
+
+    let w: Vec<_> = Counter::new().sliding_windows(3).collect();
+    assert_eq!(w, vec![vec![1, 2, 3], vec![2, 3, 4], vec![3, 4, 5]]);
+    assert_eq!(Counter::new().sliding_windows(3).size_hint(), (3, Some(3)));
+    // 剩余元素接近 usize::MAX 时上界也不会被截成 usize::MAX
+    let mut huge = (0..usize::MAX).sliding_windows(3);
+    assert_eq!(huge.size_hint(), (usize::MAX - 2, Some(usize::MAX - 2)));
+    huge.next();
+    assert_eq!(huge.size_hint(), (usize::MAX - 3, Some(usize::MAX - 3)));
+    assert_eq!((0..).sliding_windows(2).size_hint(), (usize::MAX - 1, None));
+    assert_eq!([1, 2].iter().sliding_windows(3).count(), 0);
+
+    let groups: Vec<_> = v.iter().chunk_by(|x| *x % 2).map(|(k, g)| (k, g.len())).collect();
+    assert_eq!(groups, vec![(1, 2), (0, 1), (1, 3), (0, 2), (1, 1)]);
+
+    let mixed: Vec<_> = Counter::new().interleave(vec![10, 20]).collect();
+    assert_eq!(mixed, vec![1, 10, 2, 20, 3, 4, 5]);
+
+    let dedup: Vec<_> = v.iter().copied().dedup_by_key(|x| *x).collect();
+    assert_eq!(dedup, vec![1, 2, 3, 4, 8, 9]);
+    let dedup: Vec<_> = v.iter().copied().dedup_by_key(|x| *x / 4).collect();
+    assert_eq!(dedup, vec![1, 4, 8]);
+
+    let batches: Vec<_> = Counter::new().batch(2).collect();
+    assert_eq!(batches, vec![vec![1, 2], vec![3, 4], vec![5]]);
+
+    let stepped: Vec<_> = v.iter().copied().step_by_pred(|prev, next| next - prev >= 2).collect();
+    assert_eq!(stepped, vec![1, 3, 8]);
+
+    let sums: Vec<_> = Counter::new().scan_while(0, |sum, x| sum + x, |sum| *sum < 10).collect();
+    assert_eq!(sums, vec![1, 3, 6]);
+
+    let mut p = Counter::new().peekable_n();
+    assert_eq!(p.peek_n(2), &[1, 2]);
+    assert_eq!(p.next(), Some(1));
+    assert_eq!(p.peek_n(10), &[2, 3, 4, 5]);
+    assert_eq!(p.size_hint(), (4, Some(4)));
+    assert_eq!(p.collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+
+    for n in 1..5 {
+        check_size_hint(v.iter().sliding_windows(n));
+        check_size_hint(v.iter().batch(n));
+    }
+    check_size_hint(v.iter().chunk_by(|x| **x / 3));
+    check_size_hint(v.iter().interleave(Counter::new().map(|_| &0)));
+    check_size_hint(v.iter().dedup_by_key(|x| **x));
+    check_size_hint(v.iter().step_by_pred(|a, b| **b > **a));
+    check_size_hint(v.iter().scan_while(0, |s, x| s + x, |s| *s < 15));
+    check_size_hint(v.iter().peekable_n());
+}
+
+// 名单
+// zip 会在两列长度不一致时悄悄截断，collect 到 HashMap 时同名的人会被覆盖。
+// Roster 把这两种情况都作为错误报告出来，并支持按年龄查询、导出 CSV/JSON
+#[derive(Debug, Clone, PartialEq)]
+struct Member {
+    name: String,
+    age: u32,
+}
+
+#[derive(Debug, PartialEq)]
+enum RosterError {
+    // 姓名列和年龄列长度不一致
+    LengthMismatch { names: usize, ages: usize },
+    // row 从 1 开始计数，CSV 中对应行号，按列导入时对应第几个人
+    DuplicateName { name: String, first: usize, row: usize },
+    InvalidRow { row: usize, reason: String },
+}
+impl Display for RosterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RosterError::LengthMismatch { names, ages } => write!(f, "{} 个姓名对应 {} 个年龄", names, ages),
+            RosterError::DuplicateName { name, first, row } => {
+                write!(f, "第 {} 行的 {} 与第 {} 行重名", row, name, first)
+            }
+            RosterError::InvalidRow { row, reason } => write!(f, "第 {} 行格式错误: {}", row, reason),
+        }
+    }
+}
+impl std::error::Error for RosterError {}
+
+#[derive(Debug, Default)]
+struct Roster {
+    members: Vec<Member>,
+    // 姓名 -> (下标, 来源行号)
+    index: HashMap<String, (usize, usize)>,
+}
+impl Roster {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn from_columns<N, A>(names: N, ages: A) -> Result<Roster, RosterError>
+    where
+        N: IntoIterator,
+        N::Item: Into<String>,
+        A: IntoIterator<Item = u32>,
+    {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        let ages: Vec<u32> = ages.into_iter().collect();
+        if names.len() != ages.len() {
+            return Err(RosterError::LengthMismatch { names: names.len(), ages: ages.len() });
+        }
+        let mut roster = Roster::new();
+        for (row, (name, age)) in names.into_iter().zip(ages).enumerate() {
+            roster.insert_at(name, age, row + 1)?;
+        }
+        Ok(roster)
+    }
+
+    // 每行 "姓名,年龄"，可以有 name,age 表头，空行会被忽略。
+    // 姓名中有逗号、引号、换行或者首尾空白时用双引号括起来，引号里的内容原样保留
+    fn from_csv(csv: &str) -> Result<Roster, RosterError> {
+        let records = split_csv_records(csv).map_err(|row| RosterError::InvalidRow {
+            row,
+            reason: "引号没有闭合".to_string(),
+        })?;
+        let mut roster = Roster::new();
+        for (row, fields) in records {
+            let [name, age] = fields.as_slice() else {
+                return Err(RosterError::InvalidRow { row, reason: format!("应该有 2 列，实际 {} 列", fields.len()) });
+            };
+            if row == 1 && name.eq_ignore_ascii_case("name") && age.eq_ignore_ascii_case("age") {
+                continue;
+            }
+            let age = age.parse().map_err(|_| RosterError::InvalidRow { row, reason: format!("年龄 {:?} 不是整数", age) })?;
+            roster.insert_at(name.clone(), age, row)?;
+        }
+        Ok(roster)
+    }
+
+    fn insert(&mut self, name: impl Into<String>, age: u32) -> Result<(), RosterError> {
+        let row = self.members.len() + 1;
+        self.insert_at(name.into(), age, row)
+    }
+
+    fn insert_at(&mut self, name: String, age: u32, row: usize) -> Result<(), RosterError> {
+        if let Some(&(_, first)) = self.index.get(&name) {
+            return Err(RosterError::DuplicateName { name, first, row });
+        }
+        self.index.insert(name.clone(), (self.members.len(), row));
+        self.members.push(Member { name, age });
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    fn age_of(&self, name: &str) -> Option<u32> {
+        self.index.get(name).map(|&(i, _)| self.members[i].age)
+    }
+
+    // 年龄相同时取先加入的
+    fn oldest(&self) -> Option<&Member> {
+        self.members.iter().rev().max_by_key(|m| m.age)
+    }
+
+    fn youngest(&self) -> Option<&Member> {
+        self.members.iter().rev().min_by_key(|m| m.age)
+    }
+
+    // 按 width 岁一档分组，键是每档的起始年龄
+    fn age_buckets(&self, width: u32) -> BTreeMap<u32, Vec<&str>> {
+        let width = width.max(1);
+        let mut buckets: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+        for m in &self.members {
+            buckets.entry(m.age / width * width).or_default().push(&m.name);
+        }
+        buckets
+    }
+
+    fn to_csv(&self) -> String {
+        let mut out = String::from("name,age\n");
+        for m in &self.members {
+            let name = if m.name.contains([',', '"', '\n', '\r']) || m.name.trim() != m.name {
+                format!("\"{}\"", m.name.replace('"', "\"\""))
+            } else {
+                m.name.clone()
+            };
+            out.push_str(&format!("{},{}\n", name, m.age));
+        }
+        out
+    }
+
+    fn to_json(&self) -> String {
+        let members: Vec<String> = self
+            .members
+            .iter()
+            .map(|m| format!("{{\"name\":{},\"age\":{}}}", json_string(&m.name), m.age))
+            .collect();
+        format!("[{}]", members.join(","))
+    }
+}
+
+// 拆分 CSV，返回每条记录的起始行号和字段，空行会被跳过
+// 支持双引号括起来的字段以及 "" 转义，引号里可以有逗号和换行。
+// 没有引号的字段去掉首尾空白，有引号的字段原样保留，引号外的空白被忽略。
+// 引号没有闭合时返回那条记录的起始行号
+fn split_csv_records(csv: &str) -> Result<Vec<(usize, Vec<String>)>, usize> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    // quoted：当前在引号里；was_quoted：当前字段用引号括起来过
+    let (mut quoted, mut was_quoted) = (false, false);
+    let (mut line, mut start) = (1, 1);
+    let finish = |field: &mut String, was_quoted: &mut bool| {
+        let field = std::mem::take(field);
+        if std::mem::take(was_quoted) { field } else { field.trim().to_string() }
+    };
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) => {
+                // 引号前面只有空白时，这些空白不属于字段
+                if !was_quoted && field.trim().is_empty() {
+                    field.clear();
+                }
+                quoted = true;
+                was_quoted = true;
+            }
+            (',', false) => fields.push(finish(&mut field, &mut was_quoted)),
+            ('\n', false) => {
+                let blank = fields.is_empty() && !was_quoted && field.trim().is_empty();
+                fields.push(finish(&mut field, &mut was_quoted));
+                let fields = std::mem::take(&mut fields);
+                if !blank {
+                    records.push((start, fields));
+                }
+                line += 1;
+                start = line;
+            }
+            ('\n', true) => {
+                field.push(c);
+                line += 1;
+            }
+            (c, false) if was_quoted && c.is_whitespace() => {}
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(start);
+    }
+    if !fields.is_empty() || was_quoted || !field.trim().is_empty() {
+        fields.push(finish(&mut field, &mut was_quoted));
+        records.push((start, fields));
+    }
+    Ok(records)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn roster_test() {
+    let names = ["sunface", "sunfei"];
+    let ages = [18, 18];
+    let roster = Roster::from_columns(names, ages).unwrap();
+    assert_eq!(roster.age_of("sunfei"), Some(18));
+
+    // zip 会截断，collect 会覆盖，Roster 都会报错
+    assert_eq!(
+        Roster::from_columns(["a", "b", "c"], [1, 2]).unwrap_err(),
+        RosterError::LengthMismatch { names: 3, ages: 2 }
+    );
+    assert_eq!(
+        Roster::from_columns(["a", "b", "a"], [1, 2, 3]).unwrap_err(),
+        RosterError::DuplicateName { name: "a".to_string(), first: 1, row: 3 }
+    );
+
+    let csv = "name,age\nsunface,18\n\"Zhang, San\",35\nli si,27\n\nwang,61\n";
+    let roster = Roster::from_csv(csv).unwrap();
+    assert_eq!(roster.len(), 4);
+    assert_eq!(roster.oldest().map(|m| m.name.as_str()), Some("wang"));
+    assert_eq!(roster.youngest().map(|m| m.name.as_str()), Some("sunface"));
+    let buckets = roster.age_buckets(20);
+    assert_eq!(buckets[&0], vec!["sunface"]);
+    assert_eq!(buckets[&20], vec!["Zhang, San", "li si"]);
+    assert_eq!(buckets[&60], vec!["wang"]);
+
+    // 导出后再导入得到同样的名单
+    let exported = roster.to_csv();
+    assert_eq!(Roster::from_csv(&exported).unwrap().members, roster.members);
+    // 换行、首尾空白、引号都能原样导回来
+    let tricky = Roster::from_columns(["two\nlines", "  padded ", "crlf\r\nname", "\"quoted\", too", " "], [1, 2, 3, 4, 5]).unwrap();
+    let exported = tricky.to_csv();
+    assert!(exported.contains("\"  padded \",2\n"));
+    assert_eq!(Roster::from_csv(&exported).unwrap().members, tricky.members);
+    // 跨行的记录之后，行号仍然按文本中的行计算
+    assert_eq!(
+        Roster::from_csv("\"a\nb\",1\r\nc,x\r\n").unwrap_err(),
+        RosterError::InvalidRow { row: 3, reason: "年龄 \"x\" 不是整数".to_string() }
+    );
+    assert_eq!(Roster::from_csv(" \" a \" , 7").unwrap().age_of(" a "), Some(7));
+    assert!(matches!(Roster::from_csv("a,1\n\"b,2"), Err(RosterError::InvalidRow { row: 2, .. })));
+    assert_eq!(
+        Roster::from_columns(["a\"b"], [3]).unwrap().to_json(),
+        r#"[{"name":"a\"b","age":3}]"#
+    );
+
+    assert_eq!(
+        Roster::from_csv("a,1\nb,x").unwrap_err(),
+        RosterError::InvalidRow { row: 2, reason: "年龄 \"x\" 不是整数".to_string() }
+    );
+    assert!(matches!(Roster::from_csv("a,1,2"), Err(RosterError::InvalidRow { row: 1, .. })));
+    assert!(matches!(Roster::from_csv("a,1\nb,2\na,3"), Err(RosterError::DuplicateName { first: 1, row: 3, .. })));
+    let mut roster = Roster::new();
+    roster.insert("x", 1).unwrap();
+    assert!(roster.insert("x", 2).is_err());
+}
@@ -4,41 +4,664 @@
 // 对于某些场景，只有 newtype 可以很好地解决
 // 隐藏内部类型的细节
 
-use std::{fmt::Display, ops::Add};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, LazyLock, Mutex, RwLock};
+use std::thread;
+use std::str::FromStr;
+use rust34_1_heap::{measure, HeapSize, TrackingAlloc};
+use std::{fmt::Display, ops::{Add, Deref, DerefMut, Div, Mul, Sub}};
 
 // 为外部类型实现外部特征
 // 避免孤儿规则
-struct Wrapper(Vec<String>);
-impl Display for Wrapper {
+// Wrapper 同时也是一个可以正常使用的集合：通过 Deref 可以直接使用切片的方法，
+// 支持 push、迭代、collect 和 extend。开启 serde 特性后按普通数组序列化
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+struct Wrapper<T>(Vec<T>);
+impl<T> Wrapper<T> {
+    fn new() -> Self {
+        Wrapper(Vec::new())
+    }
+
+    fn push(&mut self, value: T) {
+        self.0.push(value);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+
+    fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+
+    // 按指定的分隔符和括号输出，不受当前语言影响
+    fn display(&self) -> WrapperDisplay<'_, T> {
+        WrapperDisplay { items: &self.0, separator: ", ", open: "[", close: "]" }
+    }
+}
+impl<T: Display> Display for Wrapper<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[{}]", self.0.join(", "))
+        self.fmt_localized(&current_catalogue(), f)
+    }
+}
+impl<T: Display> LocalizedDisplay for Wrapper<T> {
+    fn fmt_localized(&self, catalogue: &Catalogue, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", catalogue.list(&self.0))
     }
 }
+impl<T> Deref for Wrapper<T> {
+    type Target = [T];
 
-// 更好的可读性及类型异化
-#[derive(Clone, Copy)]
-struct Meters(u32);
-impl Display for Meters {
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+impl<T> DerefMut for Wrapper<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+}
+impl<T> From<Vec<T>> for Wrapper<T> {
+    fn from(v: Vec<T>) -> Self {
+        Wrapper(v)
+    }
+}
+impl<T> FromIterator<T> for Wrapper<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Wrapper(iter.into_iter().collect())
+    }
+}
+impl<T> Extend<T> for Wrapper<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+impl<T> IntoIterator for Wrapper<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+impl<'a, T> IntoIterator for &'a Wrapper<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+impl<'a, T> IntoIterator for &'a mut Wrapper<T> {
+    type Item = &'a mut T;
+    type IntoIter = std::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
+// Wrapper 的自定义输出格式
+struct WrapperDisplay<'a, T> {
+    items: &'a [T],
+    separator: &'a str,
+    open: &'a str,
+    close: &'a str,
+}
+impl<'a, T> WrapperDisplay<'a, T> {
+    fn separator(mut self, separator: &'a str) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    fn brackets(mut self, open: &'a str, close: &'a str) -> Self {
+        self.open = open;
+        self.close = close;
+        self
+    }
+}
+impl<T: Display> Display for WrapperDisplay<'_, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "距离{}米", self.0)
+        f.write_str(self.open)?;
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                f.write_str(self.separator)?;
+            }
+            write!(f, "{}", item)?;
+        }
+        f.write_str(self.close)
+    }
+}
+
+// 堆内存统计用 rust34_1_heap 里的 HeapSize，装上它的分配器后可以和实际分配的字节数对比
+#[global_allocator]
+static GLOBAL: TrackingAlloc = TrackingAlloc;
+
+// newtype 没有额外开销，堆内存就是里面的 Vec
+impl<T: HeapSize> HeapSize for Wrapper<T> {
+    fn heap_size_shallow(&self) -> usize {
+        self.0.heap_size_shallow()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.0.heap_size_deep()
     }
 }
-impl Add for Meters {
-    type Output = Self;
 
-    fn add(self, rhs: Self) -> Self::Output {
-        Self(self.0 + rhs.0)
+fn wrapper_test() {
+    let mut w = Wrapper::new();
+    w.push(3);
+    w.extend([1, 2]);
+    // Deref 到切片
+    assert_eq!(w.len(), 3);
+    w.sort();
+    assert_eq!(w.first(), Some(&1));
+    for x in &mut w {
+        *x *= 10;
+    }
+    assert_eq!(w.iter().sum::<i32>(), 60);
+    assert_eq!(w.to_string(), "[10, 20, 30]");
+    assert_eq!(w.display().separator(" | ").brackets("<", ">").to_string(), "<10 | 20 | 30>");
+    assert_eq!(w.display().brackets("", "").to_string(), "10, 20, 30");
+
+    let words: Wrapper<String> = "hello world".split(' ').map(String::from).collect();
+    assert_eq!(words, Wrapper(vec!["hello".to_string(), "world".to_string()]));
+    // HeapSize 和分配器实际记下的字节数一致
+    let (measured, stats) = measure(|| (0..20).map(|i| i.to_string()).collect::<Wrapper<String>>());
+    assert_eq!(measured.heap_size_deep() as isize, stats.live_bytes());
+    assert!(measured.heap_size_shallow() < measured.heap_size_deep());
+    let (numbers, stats) = measure(|| {
+        let mut numbers = Wrapper::new();
+        numbers.extend(0..100u64);
+        numbers
+    });
+    assert_eq!(numbers.heap_size_deep() as isize, stats.live_bytes());
+    assert_eq!(Wrapper::<String>::new().heap_size_deep(), 0);
+    let lens: Vec<usize> = words.into_iter().map(|s| s.len()).collect();
+    assert_eq!(lens, vec![5, 5]);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&w).unwrap();
+        assert_eq!(json, "[10,20,30]");
+        let back: Wrapper<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, w);
     }
 }
+
+// 更好的可读性及类型异化
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Meters(u32);
 impl Meters {
-    fn calculate_distance(self, rhs: Meters) -> Self {
+    fn calculate_distance(self, rhs: Meters) -> Result<Meters, UnitError> {
         self + rhs
     }
 }
-fn calculate_distance(d1: Meters, d2: Meters) -> Meters {
+fn calculate_distance(d1: Meters, d2: Meters) -> Result<Meters, UnitError> {
     d1 + d2
 }
 
+// 带量纲的物理量
+// 每个单位都是一个 u32 的 newtype，量纲（长度、时间、速度）由关联类型 Dim 区分，
+// 不同量纲之间无法相加，也无法互相转换，这些错误在编译期就能发现。
+// 同量纲的单位之间按精确的比例换算，结果溢出或者不是整数时返回 UnitError。
+// 运算符返回 Result，溢出、除数为 0 时返回 UnitError，在 debug 和 release 下行为一致，
+// 只关心成功与否时可以用返回 Option 的 checked_* 方法
+
+#[derive(Debug, Clone, PartialEq)]
+enum UnitError {
+    Overflow,
+    DivisionByZero,
+    // 换算结果不是整数
+    Inexact,
+    Parse(String),
+}
+impl Display for UnitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitError::Overflow => write!(f, "数值溢出"),
+            UnitError::DivisionByZero => write!(f, "除数为 0"),
+            UnitError::Inexact => write!(f, "换算结果不是整数"),
+            UnitError::Parse(s) => write!(f, "无法解析 {:?}", s),
+        }
+    }
+}
+impl std::error::Error for UnitError {}
+
+// 量纲，ratio 返回单位符号相对于基本单位的比例 num/den
+trait Dimension {
+    fn ratio(symbol: &str) -> Option<(u128, u128)>;
+}
+struct Length;
+struct Time;
+struct Speed;
+impl Dimension for Length {
+    fn ratio(symbol: &str) -> Option<(u128, u128)> {
+        match symbol {
+            "m" => Some((1, 1)),
+            "km" => Some((1000, 1)),
+            // 1 英尺 = 0.3048 米
+            "ft" => Some((3048, 10000)),
+            _ => None,
+        }
+    }
+}
+impl Dimension for Time {
+    fn ratio(symbol: &str) -> Option<(u128, u128)> {
+        match symbol {
+            "s" => Some((1, 1)),
+            "min" => Some((60, 1)),
+            "h" => Some((3600, 1)),
+            _ => None,
+        }
+    }
+}
+impl Dimension for Speed {
+    fn ratio(symbol: &str) -> Option<(u128, u128)> {
+        match symbol {
+            "m/s" => Some((1, 1)),
+            "km/h" => Some((5, 18)),
+            _ => None,
+        }
+    }
+}
+
+trait Unit: Copy {
+    type Dim: Dimension;
+    const SYMBOL: &'static str;
+    fn value(self) -> u32;
+    fn from_value(v: u32) -> Self;
+
+    // 换算到同量纲的另一个单位
+    fn to<U: Unit<Dim = Self::Dim>>(self) -> Result<U, UnitError> {
+        scale::<Self::Dim, U>(self.value() as u128, 1, Self::SYMBOL)
+    }
+}
+
+// 把 mantissa/divisor 个 symbol 换算成 U
+fn scale<D: Dimension, U: Unit<Dim = D>>(mantissa: u128, divisor: u128, symbol: &str) -> Result<U, UnitError> {
+    let (from_num, from_den) = D::ratio(symbol).ok_or_else(|| UnitError::Parse(symbol.to_string()))?;
+    let (to_num, to_den) = D::ratio(U::SYMBOL).expect("unit symbol registered in its dimension");
+    let numerator = mantissa.checked_mul(from_num * to_den).ok_or(UnitError::Overflow)?;
+    let denominator = divisor
+        .checked_mul(from_den)
+        .and_then(|d| d.checked_mul(to_num))
+        .ok_or(UnitError::Overflow)?;
+    if numerator % denominator != 0 {
+        return Err(UnitError::Inexact);
+    }
+    u32::try_from(numerator / denominator).map(U::from_value).map_err(|_| UnitError::Overflow)
+}
+
+// 解析 "12.5km"、"3 min" 这样的字符串，小数部分按十进制精确计算
+fn parse_quantity<U: Unit>(s: &str) -> Result<U, UnitError> {
+    let err = || UnitError::Parse(s.to_string());
+    let t = s.trim();
+    let split = t.find(|c: char| !(c.is_ascii_digit() || c == '.')).ok_or_else(err)?;
+    let (number, symbol) = (&t[..split], t[split..].trim());
+    let (int, frac) = number.split_once('.').unwrap_or((number, ""));
+    if int.is_empty() && frac.is_empty() {
+        return Err(err());
+    }
+    let digits = format!("{}{}", int, frac);
+    let mantissa: u128 = digits.parse().map_err(|_| err())?;
+    let divisor = 10u128.checked_pow(frac.len() as u32).ok_or(UnitError::Overflow)?;
+    match scale::<U::Dim, U>(mantissa, divisor, symbol) {
+        Err(UnitError::Parse(_)) => Err(err()),
+        r => r,
+    }
+}
+
+// 为单位实现同单位之间的加减、与标量相乘，以及解析
+macro_rules! unit {
+    ($name:ident, $dim:ty, $symbol:expr) => {
+        impl Unit for $name {
+            type Dim = $dim;
+            const SYMBOL: &'static str = $symbol;
+            fn value(self) -> u32 {
+                self.0
+            }
+            fn from_value(v: u32) -> Self {
+                $name(v)
+            }
+        }
+        impl $name {
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                self.0.checked_add(rhs.0).map($name)
+            }
+            fn checked_sub(self, rhs: Self) -> Option<Self> {
+                self.0.checked_sub(rhs.0).map($name)
+            }
+            fn checked_mul(self, rhs: u32) -> Option<Self> {
+                self.0.checked_mul(rhs).map($name)
+            }
+        }
+        impl Add for $name {
+            type Output = Result<Self, UnitError>;
+            fn add(self, rhs: Self) -> Self::Output {
+                self.checked_add(rhs).ok_or(UnitError::Overflow)
+            }
+        }
+        impl Sub for $name {
+            type Output = Result<Self, UnitError>;
+            fn sub(self, rhs: Self) -> Self::Output {
+                self.checked_sub(rhs).ok_or(UnitError::Overflow)
+            }
+        }
+        impl Mul<u32> for $name {
+            type Output = Result<Self, UnitError>;
+            fn mul(self, rhs: u32) -> Self::Output {
+                self.checked_mul(rhs).ok_or(UnitError::Overflow)
+            }
+        }
+        impl FromStr for $name {
+            type Err = UnitError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                parse_quantity(s)
+            }
+        }
+        // 以单位符号作为消息键，按当前语言输出
+        impl LocalizedDisplay for $name {
+            fn fmt_localized(&self, catalogue: &Catalogue, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", catalogue.message($symbol, self.0 as u64))
+            }
+        }
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.fmt_localized(&current_catalogue(), f)
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Kilometers(u32);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Feet(u32);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Seconds(u32);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct MetersPerSecond(u32);
+
+unit!(Meters, Length, "m");
+unit!(Kilometers, Length, "km");
+unit!(Feet, Length, "ft");
+unit!(Seconds, Time, "s");
+unit!(MetersPerSecond, Speed, "m/s");
+
+// 不同量纲之间的运算，和 to() 一样不做取整，除不尽时返回 Inexact
+fn checked_div(a: u32, b: u32) -> Result<u32, UnitError> {
+    let quotient = a.checked_div(b).ok_or(UnitError::DivisionByZero)?;
+    if a % b != 0 {
+        return Err(UnitError::Inexact);
+    }
+    Ok(quotient)
+}
+impl Div<Seconds> for Meters {
+    type Output = Result<MetersPerSecond, UnitError>;
+    fn div(self, rhs: Seconds) -> Self::Output {
+        checked_div(self.0, rhs.0).map(MetersPerSecond)
+    }
+}
+impl Div<MetersPerSecond> for Meters {
+    type Output = Result<Seconds, UnitError>;
+    fn div(self, rhs: MetersPerSecond) -> Self::Output {
+        checked_div(self.0, rhs.0).map(Seconds)
+    }
+}
+// 同单位相除得到无量纲的倍数
+impl Div for Meters {
+    type Output = Result<u32, UnitError>;
+    fn div(self, rhs: Meters) -> Self::Output {
+        checked_div(self.0, rhs.0)
+    }
+}
+impl Mul<Seconds> for MetersPerSecond {
+    type Output = Result<Meters, UnitError>;
+    fn mul(self, rhs: Seconds) -> Self::Output {
+        self.0.checked_mul(rhs.0).map(Meters).ok_or(UnitError::Overflow)
+    }
+}
+impl Mul<MetersPerSecond> for Seconds {
+    type Output = Result<Meters, UnitError>;
+    fn mul(self, rhs: MetersPerSecond) -> Self::Output {
+        rhs * self
+    }
+}
+
+fn units_test() {
+    assert_eq!(Meters(10) + Meters(20), Ok(Meters(30)));
+    assert_eq!(Meters(u32::MAX).checked_add(Meters(1)), None);
+    assert_eq!(Meters(1).checked_sub(Meters(2)), None);
+    // 运算符溢出时返回错误而不是 panic
+    assert_eq!(Meters(u32::MAX) + Meters(1), Err(UnitError::Overflow));
+    assert_eq!(Seconds(1) - Seconds(2), Err(UnitError::Overflow));
+    assert_eq!(Feet(u32::MAX) * 2, Err(UnitError::Overflow));
+    assert_eq!(Kilometers(7) * 3, Ok(Kilometers(21)));
+
+    // 换算
+    assert_eq!(Kilometers(3).to::<Meters>(), Ok(Meters(3000)));
+    assert_eq!(Meters(2500).to::<Kilometers>(), Err(UnitError::Inexact));
+    assert_eq!(Feet(1250).to::<Meters>(), Ok(Meters(381)));
+    assert_eq!(Kilometers(u32::MAX).to::<Meters>(), Err(UnitError::Overflow));
+    // Seconds(1).to::<Meters>() 无法通过编译
+
+    // 量纲运算：距离 / 时间 = 速度
+    let speed = (Meters(100) / Seconds(20)).unwrap();
+    assert_eq!(speed, MetersPerSecond(5));
+    assert_eq!(speed * Seconds(60), Ok(Meters(300)));
+    assert_eq!(Seconds(60) * speed, Ok(Meters(300)));
+    assert_eq!(Meters(300) / speed, Ok(Seconds(60)));
+    assert_eq!(Meters(300) / Meters(100), Ok(3));
+    assert_eq!(MetersPerSecond(u32::MAX) * Seconds(2), Err(UnitError::Overflow));
+    // 除数为 0
+    assert_eq!(Meters(5) / Seconds(0), Err(UnitError::DivisionByZero));
+    assert_eq!(Meters(5) / MetersPerSecond(0), Err(UnitError::DivisionByZero));
+    assert_eq!(Meters(5) / Meters(0), Err(UnitError::DivisionByZero));
+    // 除不尽
+    assert_eq!(Meters(5) / Seconds(2), Err(UnitError::Inexact));
+    assert_eq!(Meters(5) / MetersPerSecond(2), Err(UnitError::Inexact));
+    assert_eq!(Meters(250) / Meters(100), Err(UnitError::Inexact));
+
+    // 解析
+    assert_eq!("12.5km".parse::<Meters>(), Ok(Meters(12500)));
+    assert_eq!(" 12.5 km ".parse::<Meters>(), Ok(Meters(12500)));
+    assert_eq!("3.5km".parse::<Kilometers>(), Err(UnitError::Inexact));
+    assert_eq!("2h".parse::<Seconds>(), Ok(Seconds(7200)));
+    assert_eq!("36km/h".parse::<MetersPerSecond>(), Ok(MetersPerSecond(10)));
+    assert_eq!("1000ft".parse::<Feet>(), Ok(Feet(1000)));
+    assert_eq!("5000000km".parse::<Meters>(), Err(UnitError::Overflow));
+    assert!(matches!("12.5kg".parse::<Meters>(), Err(UnitError::Parse(_))));
+    assert!(matches!("3s".parse::<Meters>(), Err(UnitError::Parse(_))));
+    assert!(matches!("km".parse::<Meters>(), Err(UnitError::Parse(_))));
+    assert!(matches!("1.2.3m".parse::<Meters>(), Err(UnitError::Parse(_))));
+    // 小数位太多，分母溢出
+    let long = format!("1.{}ft", "0".repeat(35));
+    assert_eq!(long.parse::<Meters>(), Err(UnitError::Overflow));
+}
+
+// 本地化输出
+// newtype 的 Display 不再写死中文，而是从当前语言的消息目录中取模板。
+// 目录里包括消息模板（区分单复数）、列表分隔符、括号和千位分隔符，
+// 内置 zh-CN 和 en-US，也可以注册自定义目录，运行时通过 set_locale 切换
+#[derive(Debug, Clone)]
+struct Plural {
+    one: String,
+    other: String,
+}
+
+#[derive(Debug, Clone)]
+struct Catalogue {
+    locale: String,
+    // 消息键 -> 模板，模板中的 {n} 会被替换为格式化后的数字
+    messages: HashMap<String, Plural>,
+    list_separator: String,
+    brackets: (String, String),
+    // 为空时不分组
+    group_separator: String,
+}
+impl Catalogue {
+    fn new(locale: &str) -> Catalogue {
+        Catalogue {
+            locale: locale.to_string(),
+            messages: HashMap::new(),
+            list_separator: ", ".to_string(),
+            brackets: ("[".to_string(), "]".to_string()),
+            group_separator: String::new(),
+        }
+    }
+
+    fn zh_cn() -> Catalogue {
+        Catalogue::new("zh-CN")
+            .with_message("m", "距离{n}米", "距离{n}米")
+            .with_message("km", "{n}千米", "{n}千米")
+            .with_message("ft", "{n}英尺", "{n}英尺")
+            .with_message("s", "{n}秒", "{n}秒")
+            .with_message("m/s", "{n}米每秒", "{n}米每秒")
+    }
+
+    fn en_us() -> Catalogue {
+        Catalogue::new("en-US")
+            .with_message("m", "{n} meter", "{n} meters")
+            .with_message("km", "{n} kilometer", "{n} kilometers")
+            .with_message("ft", "{n} foot", "{n} feet")
+            .with_message("s", "{n} second", "{n} seconds")
+            .with_message("m/s", "{n} meter per second", "{n} meters per second")
+            .with_group_separator(",")
+    }
+
+    fn with_message(mut self, key: &str, one: &str, other: &str) -> Catalogue {
+        self.messages.insert(key.to_string(), Plural { one: one.to_string(), other: other.to_string() });
+        self
+    }
+
+    fn with_list_separator(mut self, separator: &str) -> Catalogue {
+        self.list_separator = separator.to_string();
+        self
+    }
+
+    fn with_brackets(mut self, open: &str, close: &str) -> Catalogue {
+        self.brackets = (open.to_string(), close.to_string());
+        self
+    }
+
+    fn with_group_separator(mut self, separator: &str) -> Catalogue {
+        self.group_separator = separator.to_string();
+        self
+    }
+
+    // 每三位插入一个分组符
+    fn format_number(&self, n: u64) -> String {
+        let digits = n.to_string();
+        if self.group_separator.is_empty() {
+            return digits;
+        }
+        let mut out = String::new();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                out.push_str(&self.group_separator);
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    // 找不到消息时退化为 "数字 键"
+    fn message(&self, key: &str, n: u64) -> String {
+        let number = self.format_number(n);
+        match self.messages.get(key) {
+            Some(p) => {
+                let template = if n == 1 { &p.one } else { &p.other };
+                template.replace("{n}", &number)
+            }
+            None => format!("{} {}", number, key),
+        }
+    }
+
+    fn list<T: Display>(&self, items: &[T]) -> String {
+        let items: Vec<String> = items.iter().map(|i| i.to_string()).collect();
+        format!("{}{}{}", self.brackets.0, items.join(&self.list_separator), self.brackets.1)
+    }
+}
+
+// 按指定目录输出，Display 实现一般委托给它并传入当前目录
+trait LocalizedDisplay {
+    fn fmt_localized(&self, catalogue: &Catalogue, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
+
+    // 不切换全局语言，临时按某个目录输出
+    fn localized<'a>(&'a self, catalogue: &'a Catalogue) -> Localized<'a, Self> {
+        Localized(self, catalogue)
+    }
+}
+struct Localized<'a, T: ?Sized>(&'a T, &'a Catalogue);
+impl<T: LocalizedDisplay + ?Sized> Display for Localized<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_localized(self.1, f)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct UnknownLocale(String);
+
+static CATALOGUES: LazyLock<RwLock<HashMap<String, Arc<Catalogue>>>> = LazyLock::new(|| {
+    let builtin = [Catalogue::zh_cn(), Catalogue::en_us()];
+    RwLock::new(builtin.into_iter().map(|c| (c.locale.clone(), Arc::new(c))).collect())
+});
+// 默认中文
+static CURRENT: LazyLock<RwLock<Arc<Catalogue>>> = LazyLock::new(|| RwLock::new(Arc::new(Catalogue::zh_cn())));
+
+// 注册或替换一个目录，替换当前正在使用的语言时立即生效
+fn register_catalogue(catalogue: Catalogue) {
+    let catalogue = Arc::new(catalogue);
+    let mut current = CURRENT.write().unwrap();
+    if current.locale == catalogue.locale {
+        *current = Arc::clone(&catalogue);
+    }
+    CATALOGUES.write().unwrap().insert(catalogue.locale.clone(), catalogue);
+}
+
+fn set_locale(locale: &str) -> Result<(), UnknownLocale> {
+    let catalogue = CATALOGUES.read().unwrap().get(locale).cloned().ok_or_else(|| UnknownLocale(locale.to_string()))?;
+    *CURRENT.write().unwrap() = catalogue;
+    Ok(())
+}
+
+fn current_catalogue() -> Arc<Catalogue> {
+    Arc::clone(&CURRENT.read().unwrap())
+}
+
+fn locale_test() {
+    let w = Wrapper(vec![String::from("hello"), String::from("world")]);
+    assert_eq!(Meters(10).to_string(), "距离10米");
+    assert_eq!(w.to_string(), "[hello, world]");
+
+    set_locale("en-US").unwrap();
+    assert_eq!(Meters(1).to_string(), "1 meter");
+    assert_eq!(Meters(12500).to_string(), "12,500 meters");
+    assert_eq!(Feet(1).to_string(), "1 foot");
+    assert_eq!(MetersPerSecond(3).to_string(), "3 meters per second");
+
+    // 临时按其它目录输出，不影响全局
+    let zh = Catalogue::zh_cn().with_list_separator("、").with_brackets("【", "】");
+    assert_eq!(w.localized(&zh).to_string(), "【hello、world】");
+    assert_eq!(Seconds(30).localized(&zh).to_string(), "30秒");
+    assert_eq!(Seconds(30).to_string(), "30 seconds");
+
+    // 自定义目录
+    register_catalogue(Catalogue::new("fr-FR").with_message("m", "{n} mètre", "{n} mètres").with_group_separator(" "));
+    set_locale("fr-FR").unwrap();
+    assert_eq!(Meters(1000).to_string(), "1 000 mètres");
+    assert_eq!(Kilometers(2).to_string(), "2 km");
+    assert_eq!(set_locale("xx"), Err(UnknownLocale("xx".to_string())));
+
+    set_locale("zh-CN").unwrap();
+    assert_eq!(Meters(10).to_string(), "距离10米");
+}
+
 fn main() {
     // 为外部类型实现外部特征
     let w = Wrapper(
@@ -48,8 +671,8 @@ fn main() {
 
     // 更好的可读性及类型异化
     let (m1, m2) = (Meters(10), Meters(20));
-    let d = calculate_distance(m1, m2);
-    let dd = m1.calculate_distance(m2);
+    let d = calculate_distance(m1, m2).unwrap();
+    let dd = m1.calculate_distance(m2).unwrap();
     println!("{}, {}", d, dd);
 
     // 类型别名
@@ -60,10 +683,407 @@ fn main() {
 
     // let f: Box<dyn Fn() + Send + 'static> = Box::new(|| println!("hi"));
     // f是一个 Box<dyn T> 类型的特征对象，实现了 Fn() 和 Send 特征,生命周期为 'static。
-    type Thunk = Box<dyn Fn() + Send + 'static>;
     let f: Thunk = Box::new(|| println!("hi"));
 
     // type Result<T> = std::result::Result<T, std::io::Error>;
     // 其它库只需要使用 std::io::Result<T>
 
+    wrapper_test();
+    units_test();
+    locale_test();
+    event_bus_test();
+    thread_pool_test();
+}
+
+type Thunk = Box<dyn Fn() + Send + 'static>;
+
+// 事件总线
+// 组件把 Thunk 风格的闭包注册到带类型的事件键上，事件发生时按优先级依次调用。
+// 支持只触发一次的 FnOnce 处理器、通过 Subscription 取消订阅，
+// 以及把分发工作交给后台的工作线程执行
+
+// 事件键，E 是事件携带的数据类型
+// 处理器按 (名字, E 的 TypeId) 存放，同名但 E 不同的键是两个互不相干的事件
+struct EventKey<E> {
+    name: &'static str,
+    _event: PhantomData<fn(E)>,
+}
+impl<E> EventKey<E> {
+    const fn new(name: &'static str) -> Self {
+        Self { name, _event: PhantomData }
+    }
+}
+impl<E: 'static> EventKey<E> {
+    fn id(&self) -> EventId {
+        (self.name, TypeId::of::<E>())
+    }
+}
+
+type EventId = (&'static str, TypeId);
+
+// 取消订阅用的凭证
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Subscription {
+    event: EventId,
+    id: u64,
+}
+
+// 擦除了事件类型的处理器，分发时再 downcast 回 &E
+// 调用时不加锁，处理器里可以再次 emit 同一个事件，也可以被多个线程同时调用
+type Handler = Arc<dyn Fn(&(dyn Any + Send + Sync)) + Send + Sync>;
+type OnceHandler = Box<dyn FnOnce(&(dyn Any + Send + Sync)) + Send>;
+
+enum Callback {
+    Repeat(Handler),
+    Once(OnceHandler),
+}
+
+struct Registration {
+    id: u64,
+    priority: i32,
+    callback: Callback,
+}
+
+// 一次分发要调用的处理器快照，调用时不持有注册表的锁，处理器里可以再订阅、再分发
+enum Call {
+    Repeat(Handler),
+    Once(OnceHandler),
+}
+impl Call {
+    fn invoke(self, event: &(dyn Any + Send + Sync)) {
+        match self {
+            Call::Repeat(h) => h(event),
+            Call::Once(h) => h(event),
+        }
+    }
+}
+
+// 线程池
+// 固定数量的工作线程通过通道接收任务，可以执行 Thunk，也可以执行带返回值的闭包。
+// 每个任务都会返回 JobHandle，join 可以拿到结果；任务 panic 不会让工作线程退出，
+// 而是通过 JobHandle 报告出来。shutdown 或者 drop 时会等待所有线程结束
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Debug, PartialEq)]
+enum JobError {
+    // 任务 panic 了，附带 panic 信息
+    Panicked(String),
+    // 线程池已经关闭，任务没有被执行
+    Cancelled,
+}
+
+struct JobHandle<R> {
+    receiver: mpsc::Receiver<thread::Result<R>>,
+}
+impl<R> JobHandle<R> {
+    // 阻塞等待任务结束
+    fn join(self) -> Result<R, JobError> {
+        match self.receiver.recv() {
+            Ok(Ok(r)) => Ok(r),
+            Ok(Err(payload)) => Err(JobError::Panicked(panic_message(&*payload))),
+            Err(_) => Err(JobError::Cancelled),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+struct ThreadPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    panicked: Arc<AtomicUsize>,
+}
+impl ThreadPool {
+    fn new(size: usize) -> ThreadPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    // 取到任务后立刻释放锁，其它线程才能接着取
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        // 发送端关闭，线程退出
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+        ThreadPool { sender: Some(sender), workers, panicked: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    fn spawn<F, R>(&self, f: F) -> JobHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let panicked = Arc::clone(&self.panicked);
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            if result.is_err() {
+                panicked.fetch_add(1, Ordering::Relaxed);
+            }
+            // 调用方可能已经丢弃了 JobHandle
+            let _ = tx.send(result);
+        });
+        // 线程池关闭后发送失败，任务随之被丢弃，join 得到 Cancelled
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(job);
+        }
+        JobHandle { receiver: rx }
+    }
+
+    fn execute(&self, thunk: Thunk) -> JobHandle<()> {
+        self.spawn(move || thunk())
+    }
+
+    // 目前为止 panic 的任务数
+    fn panicked(&self) -> usize {
+        self.panicked.load(Ordering::Relaxed)
+    }
+
+    fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    // 不再接收新任务，等待已提交的任务执行完后回收所有线程
+    fn shutdown(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn thread_pool_test() {
+    let mut pool = ThreadPool::new(4);
+    assert_eq!(pool.size(), 4);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let counter = Arc::clone(&counter);
+            let thunk: Thunk = Box::new(move || { counter.fetch_add(1, Ordering::SeqCst); });
+            pool.execute(thunk)
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.join(), Ok(()));
+    }
+    assert_eq!(counter.load(Ordering::SeqCst), 8);
+
+    let squares: Vec<_> = (0..5u64).map(|i| pool.spawn(move || i * i)).collect();
+    let squares: Vec<_> = squares.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(squares, vec![0, 1, 4, 9, 16]);
+
+    // panic 被报告出来，工作线程依然可用
+    let bad = pool.spawn(|| -> u32 { panic!("boom") });
+    assert_eq!(bad.join(), Err(JobError::Panicked("boom".to_string())));
+    assert_eq!(pool.panicked(), 1);
+    assert_eq!(pool.spawn(|| 1).join(), Ok(1));
+
+    // 关闭时等待已提交的任务完成
+    let slow = pool.spawn(|| { thread::sleep(std::time::Duration::from_millis(20)); "done" });
+    pool.shutdown();
+    assert_eq!(slow.join(), Ok("done"));
+    assert_eq!(pool.spawn(|| 2).join(), Err(JobError::Cancelled));
+}
+
+#[derive(Default)]
+struct EventBus {
+    handlers: Mutex<HashMap<EventId, Vec<Registration>>>,
+    next_id: AtomicU64,
+    pool: Option<ThreadPool>,
+}
+impl EventBus {
+    fn new() -> EventBus {
+        Self::default()
+    }
+
+    // emit_async 会把分发交给 workers 个后台线程
+    fn with_workers(workers: usize) -> EventBus {
+        Self { pool: Some(ThreadPool::new(workers)), ..Self::default() }
+    }
+
+    fn register(&self, event: EventId, priority: i32, callback: Callback) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut handlers = self.handlers.lock().unwrap();
+        let list = handlers.entry(event).or_default();
+        // 优先级高的在前，同优先级按注册顺序
+        let pos = list.partition_point(|r| r.priority >= priority);
+        list.insert(pos, Registration { id, priority, callback });
+        Subscription { event, id }
+    }
+
+    fn subscribe<E, F>(&self, key: &EventKey<E>, priority: i32, handler: F) -> Subscription
+    where
+        E: Send + Sync + 'static,
+        F: Fn(&E) + Send + Sync + 'static,
+    {
+        let handler: Handler = Arc::new(move |e: &(dyn Any + Send + Sync)| {
+            if let Some(e) = e.downcast_ref::<E>() {
+                handler(e)
+            }
+        });
+        self.register(key.id(), priority, Callback::Repeat(handler))
+    }
+
+    // 只会被调用一次，调用后自动取消订阅
+    fn subscribe_once<E, F>(&self, key: &EventKey<E>, priority: i32, handler: F) -> Subscription
+    where
+        E: Send + Sync + 'static,
+        F: FnOnce(&E) + Send + 'static,
+    {
+        let handler: OnceHandler = Box::new(move |e: &(dyn Any + Send + Sync)| {
+            if let Some(e) = e.downcast_ref::<E>() {
+                handler(e)
+            }
+        });
+        self.register(key.id(), priority, Callback::Once(handler))
+    }
+
+    // 注册不关心事件数据的 Thunk
+    // Thunk 不是 Sync，只能放在锁里调用，所以 Thunk 里不能再分发同一个事件，否则会死锁
+    fn subscribe_thunk<E>(&self, key: &EventKey<E>, priority: i32, thunk: Thunk) -> Subscription
+    where
+        E: Send + Sync + 'static,
+    {
+        let thunk = Mutex::new(thunk);
+        self.subscribe(key, priority, move |_: &E| (thunk.lock().unwrap_or_else(|e| e.into_inner()))())
+    }
+
+    fn unsubscribe(&self, subscription: Subscription) -> bool {
+        let mut handlers = self.handlers.lock().unwrap();
+        let Some(list) = handlers.get_mut(&subscription.event) else {
+            return false;
+        };
+        let before = list.len();
+        list.retain(|r| r.id != subscription.id);
+        before != list.len()
+    }
+
+    fn handler_count<E: 'static>(&self, key: &EventKey<E>) -> usize {
+        self.handlers.lock().unwrap().get(&key.id()).map_or(0, Vec::len)
+    }
+
+    // 取出本次要调用的处理器，一次性处理器在这里被移出注册表
+    fn take_calls(&self, event: EventId) -> Vec<Call> {
+        let mut handlers = self.handlers.lock().unwrap();
+        let Some(list) = handlers.get_mut(&event) else {
+            return Vec::new();
+        };
+        let mut calls = Vec::with_capacity(list.len());
+        let mut kept = Vec::with_capacity(list.len());
+        for r in list.drain(..) {
+            match r.callback {
+                Callback::Repeat(h) => {
+                    calls.push(Call::Repeat(Arc::clone(&h)));
+                    kept.push(Registration { callback: Callback::Repeat(h), ..r });
+                }
+                Callback::Once(h) => calls.push(Call::Once(h)),
+            }
+        }
+        *list = kept;
+        calls
+    }
+
+    // 在当前线程上同步分发，返回被调用的处理器个数
+    fn emit<E: Send + Sync + 'static>(&self, key: &EventKey<E>, event: E) -> usize {
+        let calls = self.take_calls(key.id());
+        let n = calls.len();
+        for call in calls {
+            call.invoke(&event);
+        }
+        n
+    }
+
+    // 交给工作线程分发，没有配置工作线程时退化为同步分发
+    fn emit_async<E: Send + Sync + 'static>(&self, key: &EventKey<E>, event: E) -> usize {
+        let Some(pool) = &self.pool else {
+            return self.emit(key, event);
+        };
+        let calls = self.take_calls(key.id());
+        let n = calls.len();
+        pool.spawn(move || {
+            for call in calls {
+                call.invoke(&event);
+            }
+        });
+        n
+    }
+}
+
+// 消息和控件之间通过事件总线解耦
+const MESSAGE_SENT: EventKey<String> = EventKey::new("message_sent");
+const BUTTON_CLICKED: EventKey<u32> = EventKey::new("button_clicked");
+// 和 BUTTON_CLICKED 同名，但事件类型不同
+const BUTTON_CLICKED_LABEL: EventKey<String> = EventKey::new("button_clicked");
+const COUNTDOWN: EventKey<u32> = EventKey::new("countdown");
+
+fn event_bus_test() {
+    let bus = EventBus::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let l = Arc::clone(&log);
+    let low = bus.subscribe(&MESSAGE_SENT, 0, move |msg: &String| l.lock().unwrap().push(format!("low {}", msg)));
+    let l = Arc::clone(&log);
+    bus.subscribe(&MESSAGE_SENT, 10, move |msg: &String| l.lock().unwrap().push(format!("high {}", msg)));
+    let l = Arc::clone(&log);
+    bus.subscribe_once(&MESSAGE_SENT, 5, move |msg: &String| l.lock().unwrap().push(format!("once {}", msg)));
+
+    assert_eq!(bus.emit(&MESSAGE_SENT, "hi".to_string()), 3);
+    assert_eq!(bus.emit(&MESSAGE_SENT, "again".to_string()), 2);
+    assert_eq!(*log.lock().unwrap(), vec!["high hi", "once hi", "low hi", "high again", "low again"]);
+
+    assert!(bus.unsubscribe(low));
+    assert!(!bus.unsubscribe(low));
+    assert_eq!(bus.handler_count(&MESSAGE_SENT), 1);
+    assert_eq!(bus.emit(&BUTTON_CLICKED, 1), 0);
+
+    // 同名不同类型的键各自独立
+    let l = Arc::clone(&log);
+    bus.subscribe(&BUTTON_CLICKED_LABEL, 0, move |label: &String| l.lock().unwrap().push(format!("label {}", label)));
+    assert_eq!(bus.handler_count(&BUTTON_CLICKED), 0);
+    assert_eq!(bus.emit(&BUTTON_CLICKED, 1), 0);
+    assert_eq!(bus.emit(&BUTTON_CLICKED_LABEL, "ok".to_string()), 1);
+    assert_eq!(log.lock().unwrap().last().unwrap(), "label ok");
+
+    // 处理器里再次分发同一个事件
+    let bus = Arc::new(EventBus::new());
+    let counts = Arc::new(Mutex::new(Vec::new()));
+    let (weak, c) = (Arc::downgrade(&bus), Arc::clone(&counts));
+    bus.subscribe(&COUNTDOWN, 0, move |n: &u32| {
+        c.lock().unwrap().push(*n);
+        if let (Some(bus), true) = (weak.upgrade(), *n > 0) {
+            bus.emit(&COUNTDOWN, n - 1);
+        }
+    });
+    assert_eq!(bus.emit(&COUNTDOWN, 3), 1);
+    assert_eq!(*counts.lock().unwrap(), vec![3, 2, 1, 0]);
+
+    // 后台线程分发，Thunk 也可以直接注册
+    let bus = EventBus::with_workers(2);
+    let (tx, rx) = mpsc::channel();
+    let tx1 = tx.clone();
+    bus.subscribe(&BUTTON_CLICKED, 0, move |id: &u32| tx1.send(*id).unwrap());
+    let thunk: Thunk = Box::new(move || tx.send(0).unwrap());
+    bus.subscribe_thunk(&BUTTON_CLICKED, 1, thunk);
+    assert_eq!(bus.emit_async(&BUTTON_CLICKED, 7), 2);
+    let got: Vec<u32> = rx.iter().take(2).collect();
+    assert_eq!(got, vec![0, 7]);
 }
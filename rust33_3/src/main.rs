@@ -1,22 +1,229 @@
 #![allow(unused, warnings)]
-#[derive(Debug)]
+use rust33_3_derive::EnumRepr;
+
+#[derive(Debug, Clone, PartialEq)]
 enum EnumToInt {
     A(i32),
     B(i32),
 }
 
+// 带数据的枚举的编解码
+// 二进制格式为 [tag: u8][payload]，文本格式为 "成员名:payload"，例如 "A:4"，适合写日志。
+// 每个成员只能携带一个实现了 Payload 的值，用 tagged_codec! 声明 tag 即可得到实现，
+// tag 一旦发布就不要再改，新增成员使用新的 tag
+#[derive(Debug, PartialEq)]
+enum CodecError {
+    UnknownTag(u8),
+    UnknownVariant(String),
+    // 字节数不够
+    Truncated,
+    // 解码完成后还有多余的字节
+    TrailingBytes(usize),
+    InvalidText(String),
+}
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::UnknownTag(t) => write!(f, "未知的 tag {}", t),
+            CodecError::UnknownVariant(v) => write!(f, "未知的成员 {}", v),
+            CodecError::Truncated => write!(f, "数据不完整"),
+            CodecError::TrailingBytes(n) => write!(f, "末尾多出 {} 个字节", n),
+            CodecError::InvalidText(s) => write!(f, "无法解析 {:?}", s),
+        }
+    }
+}
+impl std::error::Error for CodecError {}
+
+trait Payload: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    // 返回解出的值以及消耗的字节数
+    fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError>;
+    fn to_text(&self) -> String;
+    fn from_text(s: &str) -> Result<Self, CodecError>;
+}
+macro_rules! int_payload {
+    ($($t:ty),*) => {$(
+        impl Payload for $t {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError> {
+                const N: usize = std::mem::size_of::<$t>();
+                let bytes = buf.get(..N).ok_or(CodecError::Truncated)?;
+                Ok((<$t>::from_le_bytes(bytes.try_into().unwrap()), N))
+            }
+            fn to_text(&self) -> String {
+                self.to_string()
+            }
+            fn from_text(s: &str) -> Result<Self, CodecError> {
+                s.parse().map_err(|_| CodecError::InvalidText(s.to_string()))
+            }
+        }
+    )*};
+}
+int_payload!(i8, i16, i32, i64, u8, u16, u32, u64);
+impl Payload for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError> {
+        let (len, n) = u32::decode(buf)?;
+        let bytes = buf.get(n..n + len as usize).ok_or(CodecError::Truncated)?;
+        let s = String::from_utf8(bytes.to_vec()).map_err(|e| CodecError::InvalidText(e.to_string()))?;
+        Ok((s, n + len as usize))
+    }
+    fn to_text(&self) -> String {
+        self.clone()
+    }
+    fn from_text(s: &str) -> Result<Self, CodecError> {
+        Ok(s.to_string())
+    }
+}
+impl<T: Payload> Payload for Box<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out)
+    }
+    fn decode(buf: &[u8]) -> Result<(Self, usize), CodecError> {
+        T::decode(buf).map(|(v, n)| (Box::new(v), n))
+    }
+    fn to_text(&self) -> String {
+        (**self).to_text()
+    }
+    fn from_text(s: &str) -> Result<Self, CodecError> {
+        T::from_text(s).map(Box::new)
+    }
+}
+
+trait TaggedCodec: Sized {
+    fn encode(&self) -> Vec<u8>;
+    // 必须恰好用完所有字节
+    fn decode(bytes: &[u8]) -> Result<Self, CodecError>;
+    fn to_text(&self) -> String;
+    fn from_text(s: &str) -> Result<Self, CodecError>;
+}
+
+macro_rules! tagged_codec {
+    ($name:ident { $($tag:literal => $variant:ident($ty:ty)),* $(,)? }) => {
+        impl TaggedCodec for $name {
+            fn encode(&self) -> Vec<u8> {
+                let mut out = Vec::new();
+                match self {
+                    $($name::$variant(v) => {
+                        out.push($tag);
+                        Payload::encode(v, &mut out);
+                    })*
+                }
+                out
+            }
+
+            fn decode(bytes: &[u8]) -> Result<Self, CodecError> {
+                let (&tag, rest) = bytes.split_first().ok_or(CodecError::Truncated)?;
+                let (value, used) = match tag {
+                    $($tag => {
+                        let (v, n) = <$ty as Payload>::decode(rest)?;
+                        ($name::$variant(v), n)
+                    })*
+                    t => return Err(CodecError::UnknownTag(t)),
+                };
+                match rest.len() - used {
+                    0 => Ok(value),
+                    n => Err(CodecError::TrailingBytes(n)),
+                }
+            }
+
+            fn to_text(&self) -> String {
+                match self {
+                    $($name::$variant(v) => format!("{}:{}", stringify!($variant), Payload::to_text(v)),)*
+                }
+            }
+
+            fn from_text(s: &str) -> Result<Self, CodecError> {
+                let (variant, payload) = s.split_once(':').ok_or_else(|| CodecError::InvalidText(s.to_string()))?;
+                match variant {
+                    $(stringify!($variant) => Ok($name::$variant(<$ty as Payload>::from_text(payload)?)),)*
+                    v => Err(CodecError::UnknownVariant(v.to_string())),
+                }
+            }
+        }
+    };
+}
+
+tagged_codec!(EnumToInt {
+    0 => A(i32),
+    1 => B(i32),
+});
+
+// rust31_practice 中的 Either 持有 &i32，解码时无法凭空得到引用，
+// 这里用拥有所有权的 Box<i32> 代替
+#[derive(Debug, Clone, PartialEq)]
+enum Either {
+    Num(i32),
+    Ref(Box<i32>),
+}
+tagged_codec!(Either {
+    0 => Num(i32),
+    1 => Ref(Box<i32>),
+});
+
+#[derive(Debug, Clone, PartialEq)]
+enum Message {
+    Text(String),
+    Code(u16),
+}
+tagged_codec!(Message {
+    0 => Text(String),
+    1 => Code(u16),
+});
+
+fn codec_test() {
+    let a = EnumToInt::A(4);
+    assert_eq!(a.encode(), vec![0, 4, 0, 0, 0]);
+    assert_eq!(a.to_text(), "A:4");
+    assert_eq!(EnumToInt::from_text("B:-7"), Ok(EnumToInt::B(-7)));
+
+    // 往返
+    let values = [EnumToInt::A(0), EnumToInt::A(i32::MIN), EnumToInt::B(i32::MAX)];
+    for v in values {
+        assert_eq!(EnumToInt::decode(&v.encode()), Ok(v.clone()));
+        assert_eq!(EnumToInt::from_text(&v.to_text()), Ok(v));
+    }
+    for v in [Either::Num(18), Either::Ref(Box::new(15))] {
+        assert_eq!(Either::decode(&v.encode()), Ok(v.clone()));
+        assert_eq!(Either::from_text(&v.to_text()), Ok(v));
+    }
+    for v in [Message::Text("hello:world".to_string()), Message::Code(404)] {
+        assert_eq!(Message::decode(&v.encode()), Ok(v.clone()));
+        assert_eq!(Message::from_text(&v.to_text()), Ok(v));
+    }
+
+    // 校验
+    assert_eq!(EnumToInt::decode(&[]), Err(CodecError::Truncated));
+    assert_eq!(EnumToInt::decode(&[2, 0, 0, 0, 0]), Err(CodecError::UnknownTag(2)));
+    assert_eq!(EnumToInt::decode(&[0, 1, 0]), Err(CodecError::Truncated));
+    assert_eq!(EnumToInt::decode(&[1, 1, 0, 0, 0, 9]), Err(CodecError::TrailingBytes(1)));
+    assert_eq!(Message::decode(&[0, 9, 0, 0, 0, b'h']), Err(CodecError::Truncated));
+    assert_eq!(EnumToInt::from_text("C:1"), Err(CodecError::UnknownVariant("C".to_string())));
+    assert_eq!(EnumToInt::from_text("A:x"), Err(CodecError::InvalidText("x".to_string())));
+    assert_eq!(EnumToInt::from_text("A4"), Err(CodecError::InvalidText("A4".to_string())));
+}
+
 fn main() {
     // 枚举转整数
     let a = EnumToInt::A(4);
-    match a {
+    match &a {
         EnumToInt::A(i) => println!("{}", i),
-        EnumToInt::B(_) => todo!(),
+        EnumToInt::B(i) => println!("{}", i),
     }
+    // 编码成字节或者文本
+    println!("{:?} {}", a.encode(), a.to_text());
 
 
     let x = MyEnum::C;
     let y = x as i32;
-    let z: MyEnum = unsafe { std::mem::transmute(y) };
+    // transmute 对 1..=3 以外的值是未定义行为，改用派生出的 TryFrom，非法值返回错误
+    // let z: MyEnum = unsafe { std::mem::transmute(y) };
+    let z = MyEnum::try_from(y).unwrap();
 
     // match the enum that came from an int
     match z {
@@ -24,10 +231,41 @@ fn main() {
         MyEnum::B => { println!("Found B"); }
         MyEnum::C => { println!("Found C"); }
     }
+
+    enum_repr_test();
+    codec_test();
 }
 
 // 整数转换为枚举
+#[derive(Debug, Clone, Copy, PartialEq, EnumRepr)]
 #[repr(i32)]
 enum MyEnum {
     A = 1, B, C
 }
+
+#[derive(Debug, PartialEq, EnumRepr)]
+#[repr(u8)]
+enum Level {
+    Low = 10,
+    Mid,
+    High = 200,
+}
+
+fn enum_repr_test() {
+    assert_eq!(MyEnum::try_from(2), Ok(MyEnum::B));
+    assert_eq!(MyEnum::try_from(4), Err(InvalidMyEnum(4)));
+    assert_eq!(InvalidMyEnum(0).to_string(), "0 不是合法的 MyEnum 判别值");
+    assert_eq!(i32::from(MyEnum::C), 3);
+
+    assert_eq!(MyEnum::COUNT, 3);
+    assert_eq!(MyEnum::variants().collect::<Vec<_>>(), vec![MyEnum::A, MyEnum::B, MyEnum::C]);
+    assert_eq!(MyEnum::B.name(), "B");
+    assert_eq!(MyEnum::from_name("C"), Some(MyEnum::C));
+    assert_eq!(MyEnum::from_name("D"), None);
+
+    // 显式和隐式的判别值混用
+    let values: Vec<u8> = Level::variants().map(u8::from).collect();
+    assert_eq!(values, vec![10, 11, 200]);
+    assert_eq!(Level::try_from(11), Ok(Level::Mid));
+    assert_eq!(Level::try_from(12), Err(InvalidLevel(12)));
+}
@@ -0,0 +1,112 @@
+#![allow(warnings,unused)]
+// 枚举与整数互相转换的派生宏
+// 为带 #[repr(整数类型)] 的无字段枚举生成：
+// 1. TryFrom<整数> for 枚举，非法的判别值返回 Invalid{枚举名} 错误
+// 2. From<枚举> for 整数
+// 3. COUNT、variants() 遍历所有成员，name()/from_name() 按名字查找
+// 判别值直接用 `Self::A as 整数` 取得，显式指定和隐式递增的判别值都能正确处理
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Ident};
+
+const INT_TYPES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+];
+
+#[proc_macro_derive(EnumRepr)]
+pub fn derive_enum_repr(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new_spanned(name, "EnumRepr 只能用于枚举"));
+    };
+    let repr = find_repr(&input)?;
+    for v in &data.variants {
+        if !matches!(v.fields, Fields::Unit) {
+            return Err(Error::new_spanned(v, "EnumRepr 只支持没有字段的成员"));
+        }
+    }
+
+    let error = format_ident!("Invalid{}", name);
+    let variants: Vec<&Ident> = data.variants.iter().map(|v| &v.ident).collect();
+    let names: Vec<String> = variants.iter().map(|v| v.to_string()).collect();
+    let count = variants.len();
+    let vis = &input.vis;
+    let message = format!("{{}} 不是合法的 {} 判别值", name);
+
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #vis struct #error(pub #repr);
+
+        impl ::std::fmt::Display for #error {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                write!(f, #message, self.0)
+            }
+        }
+
+        impl ::std::error::Error for #error {}
+
+        impl ::std::convert::TryFrom<#repr> for #name {
+            type Error = #error;
+
+            fn try_from(value: #repr) -> ::std::result::Result<Self, Self::Error> {
+                #(
+                    if value == #name::#variants as #repr {
+                        return ::std::result::Result::Ok(#name::#variants);
+                    }
+                )*
+                ::std::result::Result::Err(#error(value))
+            }
+        }
+
+        impl ::std::convert::From<#name> for #repr {
+            fn from(value: #name) -> #repr {
+                value as #repr
+            }
+        }
+
+        impl #name {
+            #vis const COUNT: usize = #count;
+
+            // 按声明顺序遍历所有成员
+            #vis fn variants() -> impl ::std::iter::Iterator<Item = #name> {
+                [#(#name::#variants),*].into_iter()
+            }
+
+            #vis fn name(&self) -> &'static str {
+                match self {
+                    #(#name::#variants => #names,)*
+                }
+            }
+
+            #vis fn from_name(name: &str) -> ::std::option::Option<#name> {
+                match name {
+                    #(#names => ::std::option::Option::Some(#name::#variants),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+        }
+    })
+}
+
+// 从 #[repr(...)] 中找出整数类型，忽略 align 等其它参数
+fn find_repr(input: &DeriveInput) -> syn::Result<Ident> {
+    let mut repr = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if let Some(ident) = meta.path.get_ident() {
+                if INT_TYPES.contains(&ident.to_string().as_str()) {
+                    repr = Some(ident.clone());
+                }
+            }
+            Ok(())
+        })?;
+    }
+    repr.ok_or_else(|| Error::new(Span::call_site(), "EnumRepr 需要 #[repr(整数类型)]，例如 #[repr(i32)]"))
+}
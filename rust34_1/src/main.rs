@@ -1,4 +1,14 @@
 #![allow(warnings,unused)]
+use rust34_1_heap::{counted_alloc_size, live_heap_bytes, measure, total_allocs, AllocStats, HeapSize, TrackingAlloc};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 /*
 指针是一个包含了内存地址的变量，该内存地址引用或者指向了另外的数据。
 
@@ -42,7 +52,15 @@ fn main() {
     // 此仅仅拷贝了智能指针的结构体，底层数据并没有被拷贝
     // 所有权顺利转移给 arr1，arr 不再拥有所有权
     let arr1 = arr;
+    // 可以用 measure 验证：上面的数组拷贝没有堆分配，Box 转移所有权也没有新的分配，见 heap_size_test
 
+    list_test();
+    persistent_list_test();
+    trait_fn();
+    widget_test();
+    scene_test();
+    interner_test();
+    heap_size_test();
 }
 
 // 将动态大小类型变为 Sized 固定大小类型
@@ -51,48 +69,1379 @@ fn main() {
 //     Cons(i32, List),
 //     Nil
 // }
-enum List {
-    Cons(i32, Box<List>),
+// 上面的写法编译不过，List 的大小无法确定，用 Box 包一层就变成了固定大小
+// 这里顺便改成泛型，外面再套一个结构体记录长度
+enum Link<T> {
+    Cons(T, Box<Link<T>>),
     Nil,
 }
+use Link::{Cons, Nil};
+
+pub struct List<T> {
+    head: Link<T>,
+    len: usize,
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List { head: Nil, len: 0 }
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        let old = mem::replace(&mut self.head, Nil);
+        self.head = Cons(value, Box::new(old));
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        match mem::replace(&mut self.head, Nil) {
+            Cons(value, next) => {
+                self.head = *next;
+                self.len -= 1;
+                Some(value)
+            }
+            Nil => None,
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        match &self.head {
+            Cons(value, _) => Some(value),
+            Nil => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { cur: &self.head, remaining: self.len }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut { cur: Some(&mut self.head), remaining: self.len }
+    }
+
+    // 原地反转，只调整指针，不重新分配节点
+    pub fn reverse(&mut self) {
+        let mut prev = Nil;
+        let mut cur = mem::replace(&mut self.head, Nil);
+        while let Cons(value, mut next) = cur {
+            cur = mem::replace(&mut *next, prev);
+            prev = Cons(value, next);
+        }
+        self.head = prev;
+    }
+
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> List<U> {
+        self.iter().map(f).collect()
+    }
+
+    // 把 other 接到末尾，需要走到尾部，O(n)
+    pub fn append(&mut self, mut other: List<T>) {
+        let mut tail = &mut self.head;
+        while let Cons(_, next) = tail {
+            tail = &mut **next;
+        }
+        *tail = mem::replace(&mut other.head, Nil);
+        self.len += mem::take(&mut other.len);
+    }
+}
+
+// 默认的 drop 是递归的，链表太长会把栈撑爆，这里改成循环逐个释放
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        let mut cur = mem::replace(&mut self.head, Nil);
+        while let Cons(_, mut next) = cur {
+            cur = mem::replace(&mut *next, Nil);
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        List::new()
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        let mut tail = &mut list.head;
+        for value in iter {
+            *tail = Cons(value, Box::new(Nil));
+            if let Cons(_, next) = tail {
+                tail = &mut **next;
+            }
+            list.len += 1;
+        }
+        list
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+pub struct Iter<'a, T> {
+    cur: &'a Link<T>,
+    remaining: usize,
+}
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        match self.cur {
+            Cons(value, next) => {
+                self.cur = next;
+                self.remaining -= 1;
+                Some(value)
+            }
+            Nil => None,
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+pub struct IterMut<'a, T> {
+    cur: Option<&'a mut Link<T>>,
+    remaining: usize,
+}
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<&'a mut T> {
+        // 先把 cur 拿出来，否则返回的可变引用会和 self 的借用冲突
+        match self.cur.take()? {
+            Cons(value, next) => {
+                self.cur = Some(next);
+                self.remaining -= 1;
+                Some(value)
+            }
+            Nil => None,
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+pub struct IntoIter<T>(List<T>);
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+fn list_test() {
+    let mut list = List::new();
+    list.push_front(3);
+    list.push_front(2);
+    list.push_front(1);
+    assert_eq!(list.len(), 3);
+    assert_eq!(list.front(), Some(&1));
+    assert_eq!(list.to_string(), "(1 2 3)");
+
+    for v in list.iter_mut() {
+        *v *= 10;
+    }
+    assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![10, 20, 30]);
+
+    list.reverse();
+    assert_eq!(format!("{:?}", list), "[30, 20, 10]");
+
+    let strs = list.map(|v| format!("#{}", v));
+    assert_eq!(strs.to_string(), "(#30 #20 #10)");
+
+    list.append((1..=2).collect());
+    assert_eq!(list, [30, 20, 10, 1, 2].into_iter().collect());
+    assert_eq!(list.len(), 5);
+    assert_eq!(list.into_iter().sum::<i32>(), 63);
+
+    let mut empty: List<i32> = List::new();
+    empty.reverse();
+    empty.append(List::new());
+    assert!(empty.is_empty());
+    assert_eq!(empty.pop_front(), None);
+    assert_eq!(empty.to_string(), "()");
+
+    // 一百万个节点，递归 drop 会栈溢出
+    let mut big: List<u32> = (0..1_000_000).collect();
+    big.reverse();
+    assert_eq!(big.front(), Some(&999_999));
+    assert_eq!(big.len(), 1_000_000);
+    drop(big);
+}
+
+// 持久化链表：每次 prepend 都返回新版本，旧版本保持不变，多个版本共享尾部节点
+struct Node<T> {
+    value: T,
+    next: Option<Rc<Node<T>>>,
+}
+
+pub struct PersistentList<T> {
+    head: Option<Rc<Node<T>>>,
+    len: usize,
+}
+
+impl<T> PersistentList<T> {
+    pub fn new() -> Self {
+        PersistentList { head: None, len: 0 }
+    }
+
+    pub fn prepend(&self, value: T) -> Self {
+        PersistentList {
+            head: Some(Rc::new(Node { value, next: self.head.clone() })),
+            len: self.len + 1,
+        }
+    }
+
+    // 去掉第一个元素后的版本，和原链表共享剩下的节点
+    pub fn tail(&self) -> Self {
+        match &self.head {
+            Some(node) => PersistentList { head: node.next.clone(), len: self.len - 1 },
+            None => PersistentList::new(),
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> PersistentIter<'_, T> {
+        PersistentIter { next: self.head.as_deref() }
+    }
+
+    // 两个版本是否从头开始就是同一组节点
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T> Clone for PersistentList<T> {
+    // 只增加引用计数
+    fn clone(&self) -> Self {
+        PersistentList { head: self.head.clone(), len: self.len }
+    }
+}
+
+// 只释放独占的节点，遇到还被其他版本引用的节点就停下
+impl<T> Drop for PersistentList<T> {
+    fn drop(&mut self) {
+        let mut cur = self.head.take();
+        while let Some(node) = cur {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => cur = node.next.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for PersistentList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        write!(f, ")")
+    }
+}
+
+pub struct PersistentIter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+impl<'a, T> Iterator for PersistentIter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        self.next.map(|node| {
+            self.next = node.next.as_deref();
+            &node.value
+        })
+    }
+}
+
+fn persistent_list_test() {
+    let base = PersistentList::new().prepend(3).prepend(2);
+    let a = base.prepend(1);
+    let b = base.prepend(100);
+    assert_eq!(a.to_string(), "(1 2 3)");
+    assert_eq!(b.to_string(), "(100 2 3)");
+    assert_eq!(base.to_string(), "(2 3)");
+    // a 和 b 共享 base 的节点
+    assert!(a.tail().ptr_eq(&base));
+    assert!(b.tail().ptr_eq(&base));
+    assert_eq!(Rc::strong_count(base.head.as_ref().unwrap()), 3);
+
+    drop(a);
+    assert_eq!(Rc::strong_count(base.head.as_ref().unwrap()), 2);
+    drop(base);
+    assert_eq!(b.tail().head(), Some(&2));
+    assert_eq!(b.len(), 3);
+    assert!(b.tail().tail().tail().is_empty());
+
+    let mut big = PersistentList::new();
+    for i in 0..1_000_000 {
+        big = big.prepend(i);
+    }
+    let shared = big.tail();
+    drop(big);
+    assert_eq!(shared.len(), 999_999);
+    assert_eq!(shared.head(), Some(&999_998));
+}
 
 // 特征对象
 // 想实现不同类型组成的数组只有两个办法：枚举和特征对象, 前者限制较多
-trait Draw {
-    fn draw(&self);
+// 组件不再直接 println，而是画到一块离屏的字符缓冲区 Buffer 上，
+// 容器组件通过 children/layout 组成一棵 Box<dyn Draw> 树，由 Ui 统一布局、绘制和管理焦点
+trait Draw: AsAny {
+    // 组件的标识，容器和纯文本没有
+    fn id(&self) -> Option<i64> {
+        None
+    }
+    // 期望的大小 (宽, 高)
+    fn size(&self) -> (usize, usize);
+    // 在 area 范围内绘制，超出 area 的部分会被裁掉
+    fn draw(&self, area: Rect, buf: &mut Buffer, focused: bool) {}
+    fn focusable(&self) -> bool {
+        false
+    }
+    fn children(&self) -> &[Box<dyn Draw>] {
+        &[]
+    }
+    fn children_mut(&mut self) -> &mut [Box<dyn Draw>] {
+        &mut []
+    }
+    // 从容器中取出第 index 个子组件，不是容器时返回 None
+    fn take_child(&mut self, index: usize) -> Option<Box<dyn Draw>> {
+        None
+    }
+    // 容器给每个子组件分配的区域，和 children 一一对应
+    fn layout(&self, area: Rect) -> Vec<Rect> {
+        Vec::new()
+    }
+}
+
+// 特征对象只能调用特征里的方法，想拿回具体类型要借助 Any
+// 每个 'static 类型都自动实现 AsAny，Draw 以它为父特征，实现组件时不需要额外写代码
+trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl dyn Draw {
+    fn is<T: Draw>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    fn downcast_ref<T: Draw>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    fn downcast_mut<T: Draw>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+
+    // 在以自己为根的组件树里按 id 查找
+    fn find(&self, id: i64) -> Option<&dyn Draw> {
+        if self.id() == Some(id) {
+            return Some(self);
+        }
+        self.children().iter().find_map(|child| child.find(id))
+    }
+
+    fn find_mut(&mut self, id: i64) -> Option<&mut dyn Draw> {
+        if self.id() == Some(id) {
+            return Some(self);
+        }
+        self.children_mut().iter_mut().find_map(|child| child.find_mut(id))
+    }
+
+    // 从子树中移除 id 对应的组件（不包括自己），兄弟组件保持不变
+    fn remove_descendant(&mut self, id: i64) -> Option<Box<dyn Draw>> {
+        let index = self.children().iter().position(|child| child.id() == Some(id));
+        if let Some(index) = index {
+            return self.take_child(index);
+        }
+        self.children_mut().iter_mut().find_map(|child| child.remove_descendant(id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    // 四周各缩进 padding
+    pub fn inner(&self, padding: usize) -> Rect {
+        Rect {
+            x: self.x + padding,
+            y: self.y + padding,
+            width: self.width.saturating_sub(padding * 2),
+            height: self.height.saturating_sub(padding * 2),
+        }
+    }
+
+    pub fn intersect(&self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        Rect::new(x, y, right.saturating_sub(x), bottom.saturating_sub(y))
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+// 离屏缓冲区，一个格子一个字符
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buffer {
+    width: usize,
+    height: usize,
+    cells: Vec<char>,
+}
+impl Buffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Buffer { width, height, cells: vec![' '; width * height] }
+    }
+
+    pub fn area(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<char> {
+        if x < self.width && y < self.height {
+            Some(self.cells[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, ch: char) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = ch;
+        }
+    }
+
+    // 从 (x, y) 开始写一行字，只写 clip 以内的部分
+    pub fn put_str(&mut self, clip: Rect, x: usize, y: usize, s: &str) {
+        for (i, ch) in s.chars().enumerate() {
+            if clip.contains(x + i, y) {
+                self.set(x + i, y, ch);
+            }
+        }
+    }
+
+    pub fn line(&self, y: usize) -> String {
+        self.cells[y * self.width..(y + 1) * self.width].iter().collect()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.height).map(|y| self.line(y)).collect()
+    }
+}
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lines().join("\n"))
+    }
+}
+
+// 按钮：[ OK ]，获得焦点时 [>OK<]
+struct Button {
+    id: u32,
+    label: String,
+}
+impl Button {
+    fn new(id: u32, label: &str) -> Self {
+        Button { id, label: label.to_string() }
+    }
 }
-struct Button{ id: u32 }
 impl Draw for Button {
-    fn draw(&self) {
-        println!("这是屏幕上第{}号按钮", self.id)
+    fn id(&self) -> Option<i64> {
+        Some(self.id as i64)
+    }
+    fn size(&self) -> (usize, usize) {
+        (self.label.chars().count() + 4, 1)
+    }
+    fn draw(&self, area: Rect, buf: &mut Buffer, focused: bool) {
+        let text = if focused {
+            format!("[>{}<]", self.label)
+        } else {
+            format!("[ {} ]", self.label)
+        };
+        buf.put_str(area, area.x, area.y, &text);
+    }
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+// 选择框：< 当前选项 >，获得焦点时 <[当前选项]>，宽度按最长的选项算
+struct Select {
+    id: i32,
+    options: Vec<String>,
+    selected: usize,
+}
+impl Select {
+    fn new(id: i32, options: &[&str]) -> Self {
+        Select { id, options: options.iter().map(|s| s.to_string()).collect(), selected: 0 }
+    }
+
+    fn current(&self) -> Option<&str> {
+        self.options.get(self.selected).map(|s| s.as_str())
+    }
+
+    fn select(&mut self, index: usize) {
+        if index < self.options.len() {
+            self.selected = index;
+        }
+    }
+
+    fn option_width(&self) -> usize {
+        self.options.iter().map(|s| s.chars().count()).max().unwrap_or(0)
     }
 }
-struct Select { id: i32 }
 impl Draw for Select {
-    fn draw(&self) {
-        println!("这个选择框贼难用{}", self.id)
+    fn id(&self) -> Option<i64> {
+        Some(self.id as i64)
+    }
+    fn size(&self) -> (usize, usize) {
+        (self.option_width() + 4, 1)
     }
+    fn draw(&self, area: Rect, buf: &mut Buffer, focused: bool) {
+        let current = format!("{:width$}", self.current().unwrap_or(""), width = self.option_width());
+        let text = if focused {
+            format!("<[{}]>", current)
+        } else {
+            format!("< {} >", current)
+        };
+        buf.put_str(area, area.x, area.y, &text);
+    }
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+// 纯文本，不能获得焦点
+struct Label {
+    text: String,
+}
+impl Label {
+    fn new(text: &str) -> Self {
+        Label { text: text.to_string() }
+    }
+}
+impl Draw for Label {
+    fn size(&self) -> (usize, usize) {
+        (self.text.chars().count(), 1)
+    }
+    fn draw(&self, area: Rect, buf: &mut Buffer, focused: bool) {
+        buf.put_str(area, area.x, area.y, &self.text);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Row,
+    Column,
+}
+
+// 容器：子组件按行或者按列排开，四周留 padding，相邻子组件之间留 gap
+struct Stack {
+    direction: Direction,
+    padding: usize,
+    gap: usize,
+    children: Vec<Box<dyn Draw>>,
+}
+impl Stack {
+    fn row() -> Self {
+        Stack { direction: Direction::Row, padding: 0, gap: 1, children: Vec::new() }
+    }
+
+    fn column() -> Self {
+        Stack { direction: Direction::Column, padding: 0, gap: 0, children: Vec::new() }
+    }
+
+    fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    fn gap(mut self, gap: usize) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    fn child(mut self, child: impl Draw + 'static) -> Self {
+        self.children.push(Box::new(child));
+        self
+    }
+}
+impl Draw for Stack {
+    fn size(&self) -> (usize, usize) {
+        let gaps = self.gap * self.children.len().saturating_sub(1);
+        let sizes = self.children.iter().map(|c| c.size());
+        let (w, h) = match self.direction {
+            Direction::Row => sizes.fold((gaps, 0), |(w, h), (cw, ch)| (w + cw, h.max(ch))),
+            Direction::Column => sizes.fold((0, gaps), |(w, h), (cw, ch)| (w.max(cw), h + ch)),
+        };
+        (w + self.padding * 2, h + self.padding * 2)
+    }
+    fn children(&self) -> &[Box<dyn Draw>] {
+        &self.children
+    }
+    fn children_mut(&mut self) -> &mut [Box<dyn Draw>] {
+        &mut self.children
+    }
+    fn take_child(&mut self, index: usize) -> Option<Box<dyn Draw>> {
+        (index < self.children.len()).then(|| self.children.remove(index))
+    }
+    fn layout(&self, area: Rect) -> Vec<Rect> {
+        let inner = area.inner(self.padding);
+        let mut offset = 0;
+        self.children
+            .iter()
+            .map(|child| {
+                let (w, h) = child.size();
+                let rect = match self.direction {
+                    Direction::Row => Rect::new(inner.x + offset, inner.y, w, inner.height),
+                    Direction::Column => Rect::new(inner.x, inner.y + offset, inner.width, h),
+                };
+                offset += match self.direction {
+                    Direction::Row => w,
+                    Direction::Column => h,
+                } + self.gap;
+                rect
+            })
+            .collect()
+    }
+}
+
+// 深度优先遍历组件树，子组件的区域会被裁剪到父组件的区域以内
+fn walk<'a>(widget: &'a dyn Draw, area: Rect, f: &mut dyn FnMut(&'a dyn Draw, Rect)) {
+    f(widget, area);
+    for (child, rect) in widget.children().iter().zip(widget.layout(area)) {
+        walk(child.as_ref(), rect.intersect(area), f);
+    }
+}
+
+// 保存组件树和焦点，焦点按遍历顺序在可聚焦的组件之间移动
+struct Ui {
+    root: Box<dyn Draw>,
+    width: usize,
+    height: usize,
+    focus: usize,
+}
+impl Ui {
+    fn new(root: impl Draw + 'static, width: usize, height: usize) -> Self {
+        Ui { root: Box::new(root), width, height, focus: 0 }
+    }
+
+    fn focusables(&self) -> Vec<&dyn Draw> {
+        let mut out = Vec::new();
+        walk(self.root.as_ref(), Rect::new(0, 0, self.width, self.height), &mut |w, _| {
+            if w.focusable() {
+                out.push(w);
+            }
+        });
+        out
+    }
+
+    fn focused(&self) -> Option<&dyn Draw> {
+        self.focusables().get(self.focus).copied()
+    }
+
+    fn focus_next(&mut self) {
+        let n = self.focusables().len();
+        if n > 0 {
+            self.focus = (self.focus + 1) % n;
+        }
+    }
+
+    fn focus_prev(&mut self) {
+        let n = self.focusables().len();
+        if n > 0 {
+            self.focus = (self.focus + n - 1) % n;
+        }
+    }
+
+    fn render(&self) -> Buffer {
+        let mut buf = Buffer::new(self.width, self.height);
+        let mut index = 0;
+        walk(self.root.as_ref(), buf.area(), &mut |w, area| {
+            let focused = w.focusable() && {
+                index += 1;
+                index - 1 == self.focus
+            };
+            w.draw(area, &mut buf, focused);
+        });
+        buf
+    }
+}
+
+// 差量输出：记住上一帧，只把变化了的格子用 ANSI 光标定位写出去
+// 第一帧或者尺寸变化时清屏后整屏重画
+struct Terminal<W: Write> {
+    out: W,
+    front: Option<Buffer>,
+}
+impl<W: Write> Terminal<W> {
+    fn new(out: W) -> Self {
+        Terminal { out, front: None }
+    }
+
+    // 返回本次写出的格子数
+    fn present(&mut self, frame: &Buffer) -> io::Result<usize> {
+        let full = match &self.front {
+            Some(front) => front.width != frame.width || front.height != frame.height,
+            None => true,
+        };
+        if full {
+            write!(self.out, "\x1b[2J")?;
+        }
+        let mut written = 0;
+        for y in 0..frame.height {
+            let mut x = 0;
+            while x < frame.width {
+                let changed = |x: usize| full || self.front.as_ref().unwrap().get(x, y) != frame.get(x, y);
+                if !changed(x) {
+                    x += 1;
+                    continue;
+                }
+                // 连续变化的格子只定位一次
+                let start = x;
+                while x < frame.width && changed(x) {
+                    x += 1;
+                }
+                let run: String = (start..x).map(|x| frame.get(x, y).unwrap()).collect();
+                write!(self.out, "\x1b[{};{}H{}", y + 1, start + 1, run)?;
+                written += x - start;
+            }
+        }
+        self.out.flush()?;
+        self.front = Some(frame.clone());
+        Ok(written)
+    }
+}
+
+// 场景：组件摆在绝对坐标上，可以互相重叠，z 大的在上面，z 相同时后加入的在上面
+// 只记录位置，大小每次都从 size() 取，组件被修改后边界会跟着变
+struct SceneItem {
+    widget: Box<dyn Draw>,
+    x: usize,
+    y: usize,
+    z: i32,
+}
+impl SceneItem {
+    fn rect(&self) -> Rect {
+        let (width, height) = self.widget.size();
+        Rect::new(self.x, self.y, width, height)
+    }
+}
+
+#[derive(Default)]
+struct Scene {
+    items: Vec<SceneItem>,
+}
+impl Scene {
+    fn new() -> Self {
+        Scene::default()
+    }
+
+    fn add(&mut self, widget: impl Draw + 'static, x: usize, y: usize, z: i32) {
+        self.items.push(SceneItem { widget: Box::new(widget), x, y, z });
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    // 包含 id 的顶层组件的下标
+    fn position(&self, id: i64) -> Option<usize> {
+        self.items.iter().position(|item| item.widget.find(id).is_some())
+    }
+
+    fn get(&self, id: i64) -> Option<&dyn Draw> {
+        self.items.iter().find_map(|item| item.widget.find(id))
+    }
+
+    fn get_mut(&mut self, id: i64) -> Option<&mut dyn Draw> {
+        self.items.iter_mut().find_map(|item| item.widget.find_mut(id))
+    }
+
+    // 按 id 找到后转成具体类型，类型不对时返回 None
+    fn get_as<T: Draw>(&self, id: i64) -> Option<&T> {
+        self.get(id)?.downcast_ref()
+    }
+
+    fn get_as_mut<T: Draw>(&mut self, id: i64) -> Option<&mut T> {
+        self.get_mut(id)?.downcast_mut()
+    }
+
+    // 移除 id 对应的组件：顶层组件从场景中移除，嵌套的只从所在的容器中移除
+    fn remove(&mut self, id: i64) -> Option<Box<dyn Draw>> {
+        if let Some(index) = self.items.iter().position(|item| item.widget.id() == Some(id)) {
+            return Some(self.items.remove(index).widget);
+        }
+        self.items.iter_mut().find_map(|item| item.widget.remove_descendant(id))
+    }
+
+    fn set_z(&mut self, id: i64, z: i32) -> bool {
+        match self.position(id) {
+            Some(index) => {
+                self.items[index].z = z;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 放到最上层
+    fn bring_to_front(&mut self, id: i64) -> bool {
+        let top = self.items.iter().map(|item| item.z).max().unwrap_or(0);
+        self.set_z(id, top + 1)
+    }
+
+    // 从下往上的绘制顺序，sort_by_key 是稳定排序，z 相同时保持加入顺序
+    fn ordered(&self) -> Vec<&SceneItem> {
+        let mut items: Vec<&SceneItem> = self.items.iter().collect();
+        items.sort_by_key(|item| item.z);
+        items
+    }
+
+    // 返回坐标处最上层、最里层的带 id 的组件
+    // 组件都是不透明的，只看最上层盖住这个点的那一个，它里面没有带 id 的组件时返回 None，
+    // 不会穿透到下面的组件
+    fn hit_test(&self, x: usize, y: usize) -> Option<i64> {
+        let item = self.ordered().into_iter().rev().find(|item| item.rect().contains(x, y))?;
+        let mut hit = None;
+        walk(item.widget.as_ref(), item.rect(), &mut |w, area| {
+            if area.contains(x, y) && w.id().is_some() {
+                hit = w.id();
+            }
+        });
+        hit
+    }
+
+    fn render(&self, width: usize, height: usize) -> Buffer {
+        let mut buf = Buffer::new(width, height);
+        for item in self.ordered() {
+            walk(item.widget.as_ref(), item.rect().intersect(buf.area()), &mut |w, area| {
+                w.draw(area, &mut buf, false);
+            });
+        }
+        buf
+    }
+}
+
+fn scene_test() {
+    let mut scene = Scene::new();
+    scene.add(Button::new(1, "OK"), 0, 0, 0);
+    scene.add(Select::new(2, &["red", "green"]), 4, 0, 0);
+    scene.add(Stack::row().child(Label::new("Pick:")).child(Button::new(3, "Go")), 0, 1, 0);
+    assert_eq!(scene.render(14, 2).lines(), vec!["[ OK< red   > ", "Pick: [ Go ]  "]);
+
+    // 按 id 查找并转成具体类型
+    assert_eq!(scene.get(2).unwrap().id(), Some(2));
+    assert!(scene.get(1).unwrap().is::<Button>());
+    assert!(scene.get_as::<Select>(1).is_none());
+    assert_eq!(scene.get_as::<Button>(3).unwrap().label, "Go");
+    scene.get_as_mut::<Select>(2).unwrap().select(1);
+    scene.get_as_mut::<Button>(3).unwrap().label = "Run".to_string();
+    assert_eq!(scene.get_as::<Select>(2).unwrap().current(), Some("green"));
+
+    // 同一层后加入的在上面，调整 z 以后 OK 盖住 Select
+    assert_eq!(scene.hit_test(4, 0), Some(2));
+    assert!(scene.bring_to_front(1));
+    assert_eq!(scene.hit_test(4, 0), Some(1));
+    // 按钮文字变长后，容器的边界也跟着变宽
+    assert_eq!(scene.render(14, 2).lines(), vec!["[ OK ]green > ", "Pick: [ Run ] "]);
+    // 嵌套在容器里的组件也能命中，Label 没有 id 所以落空
+    assert_eq!(scene.hit_test(7, 1), Some(3));
+    assert_eq!(scene.hit_test(12, 1), Some(3));
+    assert_eq!(scene.hit_test(1, 1), None);
+    assert_eq!(scene.hit_test(12, 0), Some(2));
+
+    let removed = scene.remove(1).unwrap();
+    assert!(removed.downcast_ref::<Button>().is_some());
+    assert!(scene.get(1).is_none());
+    assert!(scene.remove(1).is_none());
+    assert!(!scene.set_z(1, 5));
+    // 嵌套的 3 只从容器中移除，旁边的 Label 保留
+    assert!(scene.remove(3).unwrap().is::<Button>());
+    assert!(scene.get(3).is_none());
+    assert_eq!(scene.len(), 2);
+    assert_eq!(scene.render(14, 2).lines(), vec!["    < green > ", "Pick:         "]);
+    assert_eq!(scene.hit_test(7, 1), None);
+
+    // 没有 id 的组件也会挡住下面的组件
+    scene.add(Label::new("cover"), 4, 0, 1);
+    assert_eq!(scene.render(14, 2).lines(), vec!["    coveren > ", "Pick:         "]);
+    assert_eq!(scene.hit_test(5, 0), None);
+    assert_eq!(scene.hit_test(11, 0), Some(2));
 }
+
 // 特征也是 DST 类型，而特征对象在做的就是将 DST 类型转换为固定大小类型。
 fn trait_fn() {
-    let elems: Vec<Box<dyn Draw>> = vec![Box::new(Button{id:1}), Box::new(Select{id:2})];
+    let elems: Vec<Box<dyn Draw>> = vec![Box::new(Button::new(1, "OK")), Box::new(Select::new(2, &["a", "b"]))];
+    let mut buf = Buffer::new(20, 1);
+    let mut x = 0;
     for i in elems.iter(){
-        i.draw();
+        let (w, h) = i.size();
+        i.draw(Rect::new(x, 0, w, h), &mut buf, false);
+        x += w + 1;
     }
+    println!("{}", buf);
+}
+
+fn widget_test() {
+    let form = Stack::column()
+        .padding(1)
+        .child(Label::new("Login"))
+        .child(Stack::row().child(Label::new("Role")).child(Select::new(2, &["admin", "guest"])))
+        .child(Stack::row().gap(2).child(Button::new(1, "OK")).child(Button::new(3, "Cancel")));
+    assert_eq!(form.size(), (20, 5));
+    let mut ui = Ui::new(form, 24, 5);
+
+    let frame = ui.render();
+    assert_eq!(
+        frame.lines(),
+        vec![
+            "                        ",
+            " Login                  ",
+            " Role <[admin]>         ",
+            " [ OK ]  [ Cancel ]     ",
+            "                        ",
+        ]
+    );
+
+    // 焦点按树的顺序移动，首尾相接
+    ui.focus_next();
+    assert_eq!(ui.render().line(3), " [>OK<]  [ Cancel ]     ");
+    ui.focus_next();
+    ui.focus_next();
+    assert_eq!(ui.focus, 0);
+    ui.focus_prev();
+    assert_eq!(ui.render().line(3), " [ OK ]  [>Cancel<]     ");
+    assert_eq!(ui.focused().unwrap().size(), (10, 1));
+
+    // 空间不够时会被裁掉
+    let small = Ui::new(Stack::row().child(Button::new(1, "OK")).child(Button::new(2, "Cancel")), 10, 1);
+    assert_eq!(small.render().lines(), vec!["[>OK<] [ C"]);
+
+    // 差量输出
+    let mut term = Terminal::new(Vec::new());
+    ui.focus = 0;
+    assert_eq!(term.present(&ui.render()).unwrap(), 24 * 5);
+    assert_eq!(term.present(&ui.render()).unwrap(), 0);
+    term.out.clear();
+    ui.focus_next();
+    // Select 和 OK 两处各变化了两个字符
+    assert_eq!(term.present(&ui.render()).unwrap(), 4);
+    let out = String::from_utf8(term.out.clone()).unwrap();
+    assert_eq!(out, "\x1b[3;8H \x1b[3;14H \x1b[4;3H>\x1b[4;6H<");
+    // 尺寸变了就整屏重画
+    assert_eq!(term.present(&Buffer::new(3, 2)).unwrap(), 6);
 }
 
 // Box::leak
 // 消费掉 Box 并且强制目标值从内存中泄漏
 // 可以把一个 String 类型，变成一个 'static 生命周期的 &str 类型
-fn gen_static_str() -> &'static str{
+fn leak_static_str() -> &'static str{
     let mut s = String::new();
     s.push_str("hello, world");
     // 返回 'static &str 字符串切片
     Box::leak(s.into_boxed_str())
 }
+// 上面每调用一次就泄漏一份，长期运行的程序内存会一直涨
+// 改成放进全局的字符串池，相同的字符串只保存一份
+fn gen_static_str() -> &'static str{
+    let mut s = String::new();
+    s.push_str("hello, world");
+    intern_static(&s)
+}
 // 真正具有'static生命周期的往往都是编译期就创建的值，
 // 例如 let v = "hello, world"，这里 v 是直接打包到二进制可执行文件中的，
 // 再比如 const 常量。
 
 // 使用场景：
-// 需要一个在运行期初始化的值，但是可以全局有效，也就是和整个程序活得一样久
\ No newline at end of file
+// 需要一个在运行期初始化的值，但是可以全局有效，也就是和整个程序活得一样久
+
+// 字符串池（interner）
+// 字符串按块（chunk）追加保存，块写满了就换一个更大的新块，旧块原样保留，
+// 所以已经交出去的 &str 不会因为扩容而失效，整个池子 drop 时一次性释放。
+// 相同的字符串只保存一份，可以用 Symbol 这样的小句柄代替字符串做比较和哈希
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InternerStats {
+    // 不同字符串的个数
+    pub strings: usize,
+    // 实际保存的字节数
+    pub bytes: usize,
+    // 所有块一共申请的字节数
+    pub capacity: usize,
+    // intern 被调用的次数，减去 strings 就是去重省下的次数
+    pub requests: usize,
+}
+
+#[derive(Default)]
+struct Pool {
+    // 这里的 'static 是假的，实际只和 Pool 活得一样久，绝不能直接交给外部
+    map: HashMap<&'static str, Symbol>,
+    strs: Vec<&'static str>,
+    chunk: String,
+    full: Vec<String>,
+    requests: usize,
+}
+impl Pool {
+    fn intern(&mut self, s: &str) -> (Symbol, &'static str) {
+        self.requests += 1;
+        if let Some((&stored, &sym)) = self.map.get_key_value(s) {
+            return (sym, stored);
+        }
+        let stored = self.alloc(s);
+        let sym = Symbol(self.strs.len() as u32);
+        self.map.insert(stored, sym);
+        self.strs.push(stored);
+        (sym, stored)
+    }
+
+    fn alloc(&mut self, s: &str) -> &'static str {
+        if self.chunk.capacity() - self.chunk.len() < s.len() {
+            let cap = (self.chunk.capacity().max(s.len()) + 1).next_power_of_two().max(64);
+            let old = mem::replace(&mut self.chunk, String::with_capacity(cap));
+            if !old.is_empty() {
+                self.full.push(old);
+            }
+        }
+        let start = self.chunk.len();
+        // 容量足够，push_str 不会重新分配，前面交出去的切片仍然有效
+        self.chunk.push_str(s);
+        let stored: &str = &self.chunk[start..];
+        // SAFETY: chunk 的堆内存在 Pool drop 之前不会移动也不会释放（换块时旧块移到 full），
+        // 对外只以 &self 的生命周期交出去
+        unsafe { &*(stored as *const str) }
+    }
+
+    fn resolve(&self, sym: Symbol) -> Option<&'static str> {
+        self.strs.get(sym.0 as usize).copied()
+    }
+
+    fn stats(&self) -> InternerStats {
+        InternerStats {
+            strings: self.strs.len(),
+            bytes: self.strs.iter().map(|s| s.len()).sum(),
+            capacity: self.chunk.capacity() + self.full.iter().map(|c| c.capacity()).sum::<usize>(),
+            requests: self.requests,
+        }
+    }
+}
+
+// 单线程使用的字符串池，通过 &self 就能放入新字符串，返回的 &str 和池子活得一样久
+#[derive(Default)]
+pub struct Interner {
+    pool: RefCell<Pool>,
+}
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    pub fn intern(&self, s: &str) -> Symbol {
+        self.pool.borrow_mut().intern(s).0
+    }
+
+    // 放入字符串并返回池子里的那一份
+    pub fn alloc(&self, s: &str) -> &str {
+        self.pool.borrow_mut().intern(s).1
+    }
+
+    // 其他池子的 Symbol 可能找不到
+    pub fn resolve(&self, sym: Symbol) -> Option<&str> {
+        self.pool.borrow().resolve(sym)
+    }
+
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.pool.borrow().map.get(s).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool.borrow().strs.len()
+    }
+
+    pub fn stats(&self) -> InternerStats {
+        self.pool.borrow().stats()
+    }
+}
+
+// 全局字符串池，多线程共用，里面的字符串和程序活得一样久
+static GLOBAL_POOL: LazyLock<Mutex<Pool>> = LazyLock::new(|| Mutex::new(Pool::default()));
+
+pub fn intern_static(s: &str) -> &'static str {
+    // 全局池永远不会 drop，这里的 'static 是真的
+    GLOBAL_POOL.lock().unwrap().intern(s).1
+}
+
+pub fn intern_global(s: &str) -> Symbol {
+    GLOBAL_POOL.lock().unwrap().intern(s).0
+}
+
+pub fn resolve_global(sym: Symbol) -> Option<&'static str> {
+    GLOBAL_POOL.lock().unwrap().resolve(sym)
+}
+
+pub fn global_interner_stats() -> InternerStats {
+    GLOBAL_POOL.lock().unwrap().stats()
+}
+
+fn interner_test() {
+    let interner = Interner::new();
+    let a = interner.intern("hello");
+    let b = interner.intern("world");
+    let c = interner.intern("hello");
+    assert_eq!(a, c);
+    assert_ne!(a, b);
+    assert_eq!(interner.resolve(b), Some("world"));
+    assert_eq!(interner.get("world"), Some(b));
+    assert_eq!(interner.get("rust"), None);
+    assert_eq!(interner.resolve(Symbol(99)), None);
+
+    // 相同的字符串拿到的是同一块内存
+    let s1 = interner.alloc("hello");
+    let s2 = interner.alloc(&(String::from("hel") + "lo"));
+    assert!(std::ptr::eq(s1, s2));
+
+    // 写满好几个块以后，早先交出去的切片依然有效
+    let first = interner.alloc("first");
+    for i in 0..1000 {
+        interner.intern(&format!("key-{}", i));
+    }
+    assert_eq!(first, "first");
+    assert_eq!(interner.len(), 1003);
+    let stats = interner.stats();
+    assert_eq!(stats.strings, 1003);
+    assert_eq!(stats.requests, 1006);
+    assert!(stats.capacity >= stats.bytes);
+
+    // 重复放入不会增长
+    let before = interner.stats();
+    for _ in 0..10_000 {
+        interner.intern("key-1");
+    }
+    let after = interner.stats();
+    assert_eq!((after.strings, after.bytes, after.capacity), (before.strings, before.bytes, before.capacity));
+    drop(interner);
+
+    // gen_static_str 反复调用不再泄漏
+    let first = gen_static_str();
+    let before = global_interner_stats();
+    for _ in 0..1000 {
+        assert!(std::ptr::eq(gen_static_str(), first));
+    }
+    assert_eq!(global_interner_stats().bytes, before.bytes);
+
+    // 多线程共用全局池
+    let handles: Vec<_> = (0..4)
+        .map(|_| std::thread::spawn(|| (0..100).map(|i| intern_global(&format!("t{}", i))).collect::<Vec<_>>()))
+        .collect();
+    let results: Vec<Vec<Symbol>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert!(results.windows(2).all(|w| w[0] == w[1]));
+    assert_eq!(resolve_global(results[0][42]), Some("t42"));
+}
+
+// 统计堆分配的全局分配器和 HeapSize 特征放在 rust34_1_heap 里，rust33_1、rust34_2、rust34_5 也在用，
+// 每个程序自己决定要不要装上这个分配器
+#[global_allocator]
+static GLOBAL: TrackingAlloc = TrackingAlloc;
+
+// 每个节点一个 Box<Link<T>>
+impl<T: HeapSize> HeapSize for List<T> {
+    fn heap_size_shallow(&self) -> usize {
+        self.len * mem::size_of::<Link<T>>()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + self.iter().map(|v| v.heap_size_deep()).sum::<usize>()
+    }
+}
+
+// 共享的尾部会被每个版本都算一遍
+impl<T: HeapSize> HeapSize for PersistentList<T> {
+    fn heap_size_shallow(&self) -> usize {
+        self.len * counted_alloc_size::<Node<T>>()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + self.iter().map(|v| v.heap_size_deep()).sum::<usize>()
+    }
+}
+
+impl HeapSize for Button {
+    fn heap_size_shallow(&self) -> usize {
+        0
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.label.heap_size_deep()
+    }
+}
+
+impl HeapSize for Select {
+    fn heap_size_shallow(&self) -> usize {
+        0
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.options.heap_size_deep()
+    }
+}
+
+impl HeapSize for Label {
+    fn heap_size_shallow(&self) -> usize {
+        0
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.text.heap_size_deep()
+    }
+}
+
+fn heap_size_test() {
+    // 栈上的数组拷贝不会碰堆
+    let (arr1, stats) = measure(|| {
+        let arr = [0u32; 1000];
+        let arr1 = arr;
+        arr1
+    });
+    assert_eq!(stats, AllocStats::default());
+    assert_eq!(arr1.heap_size_deep(), 0);
+
+    // Box::new 分配一次，转移所有权只拷贝指针
+    let (arr, stats) = measure(|| Box::new([0u32; 1000]));
+    assert_eq!((stats.allocs, stats.bytes_allocated), (1, 4000));
+    assert_eq!(arr.heap_size_deep(), 4000);
+    let (arr1, stats) = measure(move || {
+        let arr1 = arr;
+        arr1
+    });
+    assert_eq!(stats, AllocStats::default());
+    let (_, stats) = measure(move || drop(arr1));
+    assert_eq!((stats.deallocs, stats.bytes_freed), (1, 4000));
+
+    // HeapSize 和分配器实际记下的字节数一致
+    let (list, stats) = measure(|| (0..100u64).map(|i| i.to_string()).collect::<List<String>>());
+    assert_eq!(list.heap_size_deep() as isize, stats.live_bytes());
+    assert_eq!(list.heap_size_shallow(), 100 * mem::size_of::<Link<String>>());
+
+    let (v, stats) = measure(|| {
+        let mut v = Vec::with_capacity(4);
+        v.push(Box::new(1u8));
+        v.push(Box::new(2u8));
+        v
+    });
+    assert_eq!(v.heap_size_deep() as isize, stats.live_bytes());
+    assert_eq!(v.heap_size_shallow(), 4 * mem::size_of::<Box<u8>>());
+
+    let (rc, stats) = measure(|| Rc::new(String::from("shared")));
+    assert_eq!(rc.heap_size_deep() as isize, stats.live_bytes());
+    // clone 只加计数
+    let (rc2, stats) = measure(|| Rc::clone(&rc));
+    assert_eq!(stats.allocs, 0);
+    let (arc, stats) = measure(|| Arc::new(vec![1u16, 2, 3]));
+    assert_eq!(arc.heap_size_deep() as isize, stats.live_bytes());
+
+    let (plist, stats) = measure(|| PersistentList::new().prepend(1i32).prepend(2));
+    assert_eq!(plist.heap_size_deep() as isize, stats.live_bytes());
+
+    let (select, stats) = measure(|| Select::new(1, &["a", "bb"]));
+    assert_eq!(select.heap_size_deep() as isize, stats.live_bytes());
+
+    // 全部释放
+    let (_, stats) = measure(move || drop((list, v, rc, rc2, arc, plist, select)));
+    assert!(stats.bytes_freed > 0);
+    assert!(live_heap_bytes() > 0 && total_allocs() > 0);
+}
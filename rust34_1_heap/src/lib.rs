@@ -0,0 +1,217 @@
+#![allow(warnings,unused)]
+// 堆内存统计，给各个章节的程序共用
+// TrackingAlloc：统计堆分配的全局分配器，需要在程序里用 #[global_allocator] 装上才会生效
+// HeapSize：值占用的堆内存，标准库常用类型的实现放在这里，各程序只需要给自己的类型实现
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// 统计堆分配的全局分配器
+// 真正的分配交给 System，这里只记账：全局计数用原子变量，另外每个线程单独记一份，
+// 这样 measure 只统计当前线程，不会被其他线程干扰
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AllocStats {
+    pub allocs: usize,
+    pub deallocs: usize,
+    pub bytes_allocated: usize,
+    pub bytes_freed: usize,
+}
+impl AllocStats {
+    pub const ZERO: AllocStats = AllocStats { allocs: 0, deallocs: 0, bytes_allocated: 0, bytes_freed: 0 };
+
+    // 仍未释放的字节数
+    pub fn live_bytes(&self) -> isize {
+        self.bytes_allocated as isize - self.bytes_freed as isize
+    }
+
+    pub fn since(&self, earlier: AllocStats) -> AllocStats {
+        AllocStats {
+            allocs: self.allocs - earlier.allocs,
+            deallocs: self.deallocs - earlier.deallocs,
+            bytes_allocated: self.bytes_allocated - earlier.bytes_allocated,
+            bytes_freed: self.bytes_freed - earlier.bytes_freed,
+        }
+    }
+}
+
+pub struct TrackingAlloc;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // const 初始化、没有析构函数，访问时不会再去分配内存
+    static THREAD_STATS: Cell<AllocStats> = const { Cell::new(AllocStats::ZERO) };
+}
+
+fn record(alloc: Option<usize>, dealloc: Option<usize>) {
+    if let Some(size) = alloc {
+        LIVE_BYTES.fetch_add(size, Ordering::Relaxed);
+        TOTAL_ALLOCS.fetch_add(1, Ordering::Relaxed);
+    }
+    if let Some(size) = dealloc {
+        LIVE_BYTES.fetch_sub(size, Ordering::Relaxed);
+    }
+    // 线程退出时 thread_local 可能已经销毁，此时只记全局
+    let _ = THREAD_STATS.try_with(|stats| {
+        let mut s = stats.get();
+        if let Some(size) = alloc {
+            s.allocs += 1;
+            s.bytes_allocated += size;
+        }
+        if let Some(size) = dealloc {
+            s.deallocs += 1;
+            s.bytes_freed += size;
+        }
+        stats.set(s);
+    });
+}
+
+unsafe impl GlobalAlloc for TrackingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(Some(layout.size()), None);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record(Some(layout.size()), None);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(None, Some(layout.size()));
+    }
+
+    // realloc 记成一次释放加一次分配
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record(Some(new_size), Some(layout.size()));
+        }
+        new_ptr
+    }
+}
+
+pub fn live_heap_bytes() -> usize {
+    LIVE_BYTES.load(Ordering::Relaxed)
+}
+
+pub fn total_allocs() -> usize {
+    TOTAL_ALLOCS.load(Ordering::Relaxed)
+}
+
+// 统计 f 在当前线程上的分配情况
+pub fn measure<R>(f: impl FnOnce() -> R) -> (R, AllocStats) {
+    let before = THREAD_STATS.with(|s| s.get());
+    let result = f();
+    let after = THREAD_STATS.with(|s| s.get());
+    (result, after.since(before))
+}
+
+// 值占用的堆内存，不含值本身在栈上（或者在父结构体里）的大小
+// shallow 只算自己直接申请的堆内存，deep 再递归加上里面的值指向的堆内存。
+// Rc/Arc 共享的内存每个持有者都会算一遍
+pub trait HeapSize {
+    fn heap_size_shallow(&self) -> usize;
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow()
+    }
+}
+
+macro_rules! no_heap {
+    ($($t:ty),*) => {$(
+        impl HeapSize for $t {
+            fn heap_size_shallow(&self) -> usize {
+                0
+            }
+        }
+    )*};
+}
+no_heap!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, str, &str);
+
+impl HeapSize for String {
+    fn heap_size_shallow(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size_shallow(&self) -> usize {
+        self.capacity() * mem::size_of::<T>()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + self.iter().map(|v| v.heap_size_deep()).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size_shallow(&self) -> usize {
+        mem::size_of::<T>()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + (**self).heap_size_deep()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size_shallow(&self) -> usize {
+        self.as_ref().map_or(0, |v| v.heap_size_shallow())
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.as_ref().map_or(0, |v| v.heap_size_deep())
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_size_shallow(&self) -> usize {
+        0
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.iter().map(|v| v.heap_size_deep()).sum()
+    }
+}
+
+// Rc/Arc 的堆内存里除了值还有强、弱两个计数
+pub fn counted_alloc_size<T>() -> usize {
+    Layout::new::<[usize; 2]>().extend(Layout::new::<T>()).unwrap().0.pad_to_align().size()
+}
+
+impl<T: HeapSize> HeapSize for Rc<T> {
+    fn heap_size_shallow(&self) -> usize {
+        counted_alloc_size::<T>()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + (**self).heap_size_deep()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Arc<T> {
+    fn heap_size_shallow(&self) -> usize {
+        counted_alloc_size::<T>()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + (**self).heap_size_deep()
+    }
+}
+
+// HashMap 的内部布局（桶数、控制字节）没有公开，这里只按 capacity 估算，是实际占用的下限
+impl<K: HeapSize, V: HeapSize> HeapSize for HashMap<K, V> {
+    fn heap_size_shallow(&self) -> usize {
+        self.capacity() * mem::size_of::<(K, V)>()
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + self.iter().map(|(k, v)| k.heap_size_deep() + v.heap_size_deep()).sum::<usize>()
+    }
+}
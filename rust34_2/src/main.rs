@@ -1,6 +1,12 @@
 #![allow(unused,warnings)]
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
 use std::ops::{Deref,DerefMut,Drop};
+use std::ptr::{self, NonNull};
 use std::rc::Rc;
+use rust34_1_heap::{measure, HeapSize, TrackingAlloc};
 // 智能指针的名称来源，主要就在于它实现了 Deref 和 Drop 特征
 // Deref解引用 Deref可以让智能指针像引用那样工作
 // 这样可以写出同时支持智能指针和引用的代码，例如 *T
@@ -20,23 +26,436 @@ impl Person {
     }
 }
 
-struct MyBox<T>(T);
+// 堆内存统计用 rust34_1_heap 里的 HeapSize，装上它的分配器后可以和实际分配的字节数对比
+#[global_allocator]
+static GLOBAL: TrackingAlloc = TrackingAlloc;
+
+// Person 本身不在堆上申请内存，name 指向的缓冲区算在 deep 里
+impl HeapSize for Person {
+    fn heap_size_shallow(&self) -> usize {
+        0
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.name.heap_size_deep() + self.age.heap_size_deep()
+    }
+}
+
+// 最简单的写法 struct MyBox<T>(T) 只是把值包了一层，值还是在栈上
+// 这里用 std::alloc 真正把值放到堆上，自己负责在 Drop 里释放，和 Box<T> 一样
+// T 可以是 str、[T]、dyn Trait 这样的动态大小类型，此时 ptr 是胖指针
+struct MyBox<T: ?Sized> {
+    ptr: NonNull<T>,
+    // 告诉编译器 MyBox 拥有一个 T，drop 检查时会考虑 T
+    _owns: PhantomData<T>,
+}
+unsafe impl<T: ?Sized + Send> Send for MyBox<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for MyBox<T> {}
+
 impl<T> MyBox<T> {
     fn new(x: T) -> MyBox<T> {
-        MyBox(x)
+        let layout = Layout::new::<T>();
+        // 零大小类型不需要分配内存
+        let ptr = if layout.size() == 0 {
+            NonNull::dangling()
+        } else {
+            let raw = unsafe { alloc::alloc(layout) } as *mut T;
+            NonNull::new(raw).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+        unsafe { ptr.as_ptr().write(x) };
+        MyBox { ptr, _owns: PhantomData }
+    }
+
+    // 把值移回栈上并释放堆内存
+    fn into_inner(this: Self) -> T {
+        let layout = Layout::new::<T>();
+        let value = unsafe { ptr::read(this.ptr.as_ptr()) };
+        unsafe { dealloc_for(this.ptr, layout) };
+        std::mem::forget(this);
+        value
+    }
+}
+
+impl<T: ?Sized> MyBox<T> {
+    fn into_raw(this: Self) -> *mut T {
+        let raw = this.ptr.as_ptr();
+        std::mem::forget(this);
+        raw
+    }
+
+    // SAFETY: raw 必须来自 MyBox::into_raw，且只能用一次；
+    // 允许在中间把 *mut T 转成 *mut dyn Trait 这样的胖指针（见 my_box! 宏）
+    unsafe fn from_raw(raw: *mut T) -> Self {
+        MyBox { ptr: NonNull::new_unchecked(raw), _owns: PhantomData }
+    }
+}
+
+// 释放内存，值本身需要调用方先 drop 或者移走。
+// layout 要在 drop 或移走之前用 Layout::for_value 算好，之后不能再创建指向这个值的引用
+unsafe fn dealloc_for<T: ?Sized>(ptr: NonNull<T>, layout: Layout) {
+    if layout.size() != 0 {
+        alloc::dealloc(ptr.as_ptr() as *mut u8, layout);
+    }
+}
+
+// 分配一块能放下 len 个 T 的内存，返回首地址
+fn alloc_array<T>(len: usize) -> *mut T {
+    let layout = Layout::array::<T>(len).expect("长度溢出");
+    if layout.size() == 0 {
+        return NonNull::dangling().as_ptr();
+    }
+    let raw = unsafe { alloc::alloc(layout) } as *mut T;
+    if raw.is_null() {
+        alloc::handle_alloc_error(layout);
+    }
+    raw
+}
+
+impl From<&str> for MyBox<str> {
+    fn from(s: &str) -> Self {
+        let raw = alloc_array::<u8>(s.len());
+        unsafe {
+            ptr::copy_nonoverlapping(s.as_ptr(), raw, s.len());
+            MyBox::from_raw(ptr::slice_from_raw_parts_mut(raw, s.len()) as *mut str)
+        }
+    }
+}
+
+impl<T: Clone> From<&[T]> for MyBox<[T]> {
+    fn from(items: &[T]) -> Self {
+        let raw = alloc_array::<T>(items.len());
+        for (i, item) in items.iter().enumerate() {
+            unsafe { raw.add(i).write(item.clone()) };
+        }
+        unsafe { MyBox::from_raw(ptr::slice_from_raw_parts_mut(raw, items.len())) }
+    }
+}
+
+// 元素直接移过来，不需要 Clone
+impl<T> From<Vec<T>> for MyBox<[T]> {
+    fn from(mut items: Vec<T>) -> Self {
+        let len = items.len();
+        let raw = alloc_array::<T>(len);
+        unsafe {
+            ptr::copy_nonoverlapping(items.as_ptr(), raw, len);
+            // 元素已经归新的内存所有，Vec 只释放自己的缓冲区
+            items.set_len(0);
+            MyBox::from_raw(ptr::slice_from_raw_parts_mut(raw, len))
+        }
     }
 }
-impl<T> Deref for MyBox<T> {
+
+// 稳定版 Rust 里自定义指针不能自动转成特征对象，借助裸指针的隐式转换完成：
+// let b: MyBox<dyn Draw> = my_box!(MyBox::new(button) => dyn Draw);
+// 这里不能用 as，as 允许任意两个 Sized 类型的指针互转（比如 *mut u8 转 *mut u64），
+// 隐式转换只接受 T 到 dyn Trait、[T; N] 到 [T] 这样的 unsize 转换，其他写法编译不过
+macro_rules! my_box {
+    ($b:expr => $t:ty) => {{
+        let raw = MyBox::into_raw($b);
+        let raw: *mut $t = raw;
+        unsafe { MyBox::from_raw(raw) }
+    }};
+}
+
+// MyBox 自己申请的就是放值的那块内存
+impl<T: ?Sized + HeapSize> HeapSize for MyBox<T> {
+    fn heap_size_shallow(&self) -> usize {
+        std::mem::size_of_val(&**self)
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.heap_size_shallow() + (**self).heap_size_deep()
+    }
+}
+
+impl<T: ?Sized> Drop for MyBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let layout = Layout::for_value(self.ptr.as_ref());
+            ptr::drop_in_place(self.ptr.as_ptr());
+            dealloc_for(self.ptr, layout);
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for MyBox<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        unsafe { self.ptr.as_ref() }
+    }
+}
+impl<T: ?Sized> DerefMut for MyBox<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MyBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+impl<T: ?Sized + fmt::Display> fmt::Display for MyBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+impl<T: ?Sized + PartialEq> PartialEq for MyBox<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+// 把值复制一份放到堆上，Clone 要求 Sized，str 和 [T] 只能单独实现
+trait CloneBox {
+    fn clone_box(&self) -> MyBox<Self>;
+}
+impl<T: Clone> CloneBox for T {
+    fn clone_box(&self) -> MyBox<T> {
+        MyBox::new(self.clone())
+    }
+}
+impl CloneBox for str {
+    fn clone_box(&self) -> MyBox<str> {
+        MyBox::from(self)
+    }
+}
+impl<T: Clone> CloneBox for [T] {
+    fn clone_box(&self) -> MyBox<[T]> {
+        MyBox::from(self)
+    }
+}
+impl<T: ?Sized + CloneBox> Clone for MyBox<T> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
     }
 }
 
+// 写时复制：先借用着别人的数据，第一次需要修改时才复制一份到堆上
+enum CowBox<'a, T: ?Sized> {
+    Borrowed(&'a T),
+    Owned(MyBox<T>),
+}
+impl<'a, T: ?Sized> CowBox<'a, T> {
+    fn is_owned(&self) -> bool {
+        matches!(self, CowBox::Owned(_))
+    }
+}
+impl<'a, T: ?Sized + CloneBox> CowBox<'a, T> {
+    fn into_owned(self) -> MyBox<T> {
+        match self {
+            CowBox::Borrowed(v) => v.clone_box(),
+            CowBox::Owned(b) => b,
+        }
+    }
+}
+impl<T: ?Sized> Deref for CowBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            CowBox::Borrowed(v) => v,
+            CowBox::Owned(b) => b,
+        }
+    }
+}
+// 只有能复制的类型才能修改
+impl<T: ?Sized + CloneBox> DerefMut for CowBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        if let CowBox::Borrowed(v) = *self {
+            *self = CowBox::Owned(v.clone_box());
+        }
+        match self {
+            CowBox::Owned(b) => b,
+            CowBox::Borrowed(_) => unreachable!(),
+        }
+    }
+}
+
+// 只读：只实现 Deref，不实现 DerefMut，拿到它的人无法修改里面的值
+struct ReadOnly<T: ?Sized> {
+    inner: MyBox<T>,
+}
+impl<T> ReadOnly<T> {
+    fn new(value: T) -> Self {
+        ReadOnly { inner: MyBox::new(value) }
+    }
+}
+impl<T: ?Sized> From<MyBox<T>> for ReadOnly<T> {
+    fn from(inner: MyBox<T>) -> Self {
+        ReadOnly { inner }
+    }
+}
+impl<T: ?Sized> Deref for ReadOnly<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+// 统计解引用次数，deref 只拿到 &self，所以读次数用 Cell 记
+// 计数用关联函数读取，避免和 T 自己的方法重名（和 Rc::strong_count 一样）
+struct Tracked<T: ?Sized> {
+    reads: Cell<usize>,
+    writes: usize,
+    inner: MyBox<T>,
+}
+impl<T> Tracked<T> {
+    fn new(value: T) -> Self {
+        Tracked::from(MyBox::new(value))
+    }
+}
+impl<T: ?Sized> Tracked<T> {
+    fn reads(this: &Self) -> usize {
+        this.reads.get()
+    }
+
+    fn writes(this: &Self) -> usize {
+        this.writes
+    }
+
+    fn reset(this: &mut Self) {
+        this.reads.set(0);
+        this.writes = 0;
+    }
+}
+impl<T: ?Sized> From<MyBox<T>> for Tracked<T> {
+    fn from(inner: MyBox<T>) -> Self {
+        Tracked { reads: Cell::new(0), writes: 0, inner }
+    }
+}
+impl<T: ?Sized> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.reads.set(self.reads.get() + 1);
+        &self.inner
+    }
+}
+impl<T: ?Sized> DerefMut for Tracked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.writes += 1;
+        &mut self.inner
+    }
+}
+
+// 特征对象的例子，和 rust34_1 中的 Draw 类似
+trait Draw {
+    fn draw(&self) -> String;
+}
+#[derive(Clone)]
+struct Button {
+    id: u32,
+}
+impl Draw for Button {
+    fn draw(&self) -> String {
+        format!("button#{}", self.id)
+    }
+}
+struct Select {
+    id: i32,
+}
+impl Draw for Select {
+    fn draw(&self) -> String {
+        format!("select#{}", self.id)
+    }
+}
+
+// 记录 drop 次数，用来检查 MyBox 释放了里面的值
+struct DropCounter<'a>(&'a Cell<usize>);
+impl Drop for DropCounter<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+fn smart_pointer_test() {
+    // MyBox 在堆上
+    let mut b = MyBox::new(String::from("hello"));
+    b.push_str(", world");
+    assert_eq!(b.len(), 12);
+    display(&b);
+    let c = b.clone();
+    assert_eq!(c, b);
+    assert_eq!(MyBox::into_inner(b), "hello, world");
+    assert_eq!(*MyBox::new(()), ());
+
+    let drops = Cell::new(0);
+    drop(MyBox::new(DropCounter(&drops)));
+    assert_eq!(drops.get(), 1);
+    let counters: MyBox<[DropCounter]> = MyBox::from(vec![DropCounter(&drops), DropCounter(&drops)]);
+    assert_eq!(counters.len(), 2);
+    assert_eq!(drops.get(), 1);
+    drop(counters);
+    assert_eq!(drops.get(), 3);
+
+    // 动态大小类型
+    let s: MyBox<str> = MyBox::from("héllo");
+    assert_eq!(&*s, "héllo");
+    assert_eq!(s.chars().count(), 5);
+    let empty: MyBox<str> = MyBox::from("");
+    assert!(empty.is_empty());
+    let mut nums: MyBox<[i32]> = MyBox::from(&[3, 1, 2][..]);
+    nums.sort();
+    assert_eq!(&*nums, &[1, 2, 3]);
+    let widgets: Vec<MyBox<dyn Draw>> = vec![my_box!(MyBox::new(Button { id: 1 }) => dyn Draw), my_box!(MyBox::new(Select { id: 2 }) => dyn Draw)];
+    let drawn: Vec<String> = widgets.iter().map(|w| w.draw()).collect();
+    assert_eq!(drawn, vec!["button#1", "select#2"]);
+    let counted: MyBox<dyn Fn() -> usize> = my_box!(MyBox::new(|| 7usize) => dyn Fn() -> usize);
+    assert_eq!(counted(), 7);
+
+    // 写时复制
+    let original = String::from("shared");
+    let mut cow: CowBox<String> = CowBox::Borrowed(&original);
+    assert_eq!(cow.len(), 6);
+    assert!(!cow.is_owned());
+    cow.push('!');
+    assert!(cow.is_owned());
+    assert_eq!(&**cow, "shared!");
+    assert_eq!(original, "shared");
+    let mut text: CowBox<str> = CowBox::Borrowed("abc");
+    text.make_ascii_uppercase();
+    assert_eq!(&*text.into_owned(), "ABC");
+
+    // 只读
+    let config = ReadOnly::new(vec![1, 2, 3]);
+    assert_eq!(config.len(), 3);
+    // config.push(4); // 编译错误：ReadOnly 没有实现 DerefMut
+    let name: ReadOnly<str> = ReadOnly::from(MyBox::from("rust"));
+    assert_eq!(&*name, "rust");
+
+    // 统计读写
+    let mut tracked = Tracked::new(vec![1]);
+    tracked.push(2);
+    tracked.push(3);
+    let total: i32 = tracked.iter().sum();
+    assert_eq!(total, 6);
+    assert_eq!(tracked.len(), 3);
+    assert_eq!((Tracked::reads(&tracked), Tracked::writes(&tracked)), (2, 2));
+    Tracked::reset(&mut tracked);
+    assert_eq!(Tracked::reads(&tracked), 0);
+    let widget: Tracked<dyn Draw> = Tracked::from(my_box!(MyBox::new(Button { id: 9 }) => dyn Draw));
+    assert_eq!(widget.draw(), "button#9");
+    assert_eq!(Tracked::reads(&widget), 1);
+
+    // 堆内存统计
+    let (person, stats) = measure(|| Person::new(format!("person #{}", 42), 30));
+    assert_eq!(person.heap_size_shallow(), 0);
+    assert_eq!(person.heap_size_deep() as isize, stats.live_bytes());
+    let (boxed, stats) = measure(move || MyBox::new(person));
+    assert_eq!((stats.allocs, stats.bytes_allocated), (1, std::mem::size_of::<Person>()));
+    assert_eq!(boxed.heap_size_shallow(), stats.bytes_allocated);
+    let (name, stats) = measure(|| MyBox::<str>::from("héllo"));
+    assert_eq!(name.heap_size_deep() as isize, stats.live_bytes());
+    let (_, stats) = measure(move || drop(boxed));
+    assert_eq!(stats.deallocs, 2);
+}
+
 fn main() {
     let mut person = Person{ name:"s".to_string(), age:2 };
     person.display(3);
+    display_deref_continuous();
+    smart_pointer_test();
 }
 
 // 函数和方法中的隐式 Deref 转换
@@ -1,8 +1,14 @@
 #![allow(unused,warnings)]
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
 use std::rc::Rc;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
 /*
 Rc 与 Arc
 通过引用计数的方式，允许一个数据资源在同一时刻拥有多个所有者。
@@ -41,6 +47,9 @@ fn main() {
     rc_test();
     // rc_thread_test()
     arc_thread_test();
+    my_rc_test();
+    my_weak_test();
+    cycle_collector_test();
 }
 
 /*
@@ -110,13 +119,19 @@ fn arc_thread_test() {
     线程安全伴随着性能损耗，大部分时候我们开发的程序都在一个线程内。
     */
     let s = Arc::new(String::from("multi-thread test"));
+    let mut handles = Vec::new();
     for _ in 0..10 {
         let s = Arc::clone(&s);
         let handle = thread::spawn(move || {
             println!("{}", s)
         });
+        handles.push(handle);
+    }
+    // 等待所有线程结束，而不是靠 sleep 猜它们什么时候跑完
+    // 线程结束时各自持有的 Arc 被释放，计数回到 1
+    for handle in handles {
+        handle.join().unwrap();
     }
-    thread::sleep(Duration::from_secs(1));
     println!("{}", Arc::strong_count(&s));
 }
 /*
@@ -126,4 +141,543 @@ Rc 和 Arc 的区别在于，后者是原子化实现的引用计数，因此是
 这两者都是只读的，如果想要实现内部数据可修改，必须配合内部可变性 
 RefCell 或者互斥锁 Mutex 来一起使用。
 
-*/
\ No newline at end of file
+*/
+
+// 自己实现的 Rc 和 Weak（unsafe 部分用 `cargo +nightly miri run` 检查，见 README）
+// 堆上的 RcBox 里放着强引用计数、弱引用计数和值：
+// 强引用计数归零时 drop 值，弱引用计数归零时释放 RcBox。
+// 和标准库一样，所有强引用合起来算一个弱引用，这样最后一个强引用释放时
+// 如果没有 MyWeak，就能顺便把 RcBox 一起释放。
+// tracked 记录环检测注册表持有的弱引用个数，它们包含在 weak 里让 RcBox 保持有效，
+// 但不算进 weak_count，否则 get_mut、make_mut 在 debug 和 release 下行为会不一样
+struct RcBox<T> {
+    strong: Cell<usize>,
+    weak: Cell<usize>,
+    tracked: Cell<usize>,
+    value: ManuallyDrop<T>,
+}
+
+// 值被 drop 以后不能再创建指向整个 RcBox 的引用，计数只通过裸指针单独访问
+fn strong_cell<'a, T>(ptr: NonNull<RcBox<T>>) -> &'a Cell<usize> {
+    unsafe { &*ptr::addr_of!((*ptr.as_ptr()).strong) }
+}
+fn weak_cell<'a, T>(ptr: NonNull<RcBox<T>>) -> &'a Cell<usize> {
+    unsafe { &*ptr::addr_of!((*ptr.as_ptr()).weak) }
+}
+fn tracked_cell<'a, T>(ptr: NonNull<RcBox<T>>) -> &'a Cell<usize> {
+    unsafe { &*ptr::addr_of!((*ptr.as_ptr()).tracked) }
+}
+
+// 减少一个弱引用，归零时释放 RcBox（此时值已经被 drop 过或者被移走了）
+unsafe fn release_weak<T>(ptr: NonNull<RcBox<T>>) {
+    let weak = weak_cell(ptr);
+    weak.set(weak.get() - 1);
+    if weak.get() == 0 {
+        drop(Box::from_raw(ptr.as_ptr()));
+    }
+}
+
+// NonNull 不是 Send/Sync，所以 MyRc 也只能在单线程里使用
+struct MyRc<T> {
+    ptr: NonNull<RcBox<T>>,
+}
+
+struct MyWeak<T> {
+    ptr: NonNull<RcBox<T>>,
+}
+
+impl<T> MyRc<T> {
+    fn new(value: T) -> Self {
+        let boxed = Box::new(RcBox { strong: Cell::new(1), weak: Cell::new(1), tracked: Cell::new(0), value: ManuallyDrop::new(value) });
+        MyRc { ptr: NonNull::from(Box::leak(boxed)) }
+    }
+
+    fn strong_count(this: &Self) -> usize {
+        strong_cell(this.ptr).get()
+    }
+
+    // 不算强引用共同持有的那一个，也不算注册表持有的
+    fn weak_count(this: &Self) -> usize {
+        weak_cell(this.ptr).get() - 1 - tracked_cell(this.ptr).get()
+    }
+
+    fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    fn downgrade(this: &Self) -> MyWeak<T> {
+        let weak = weak_cell(this.ptr);
+        weak.set(weak.get() + 1);
+        MyWeak { ptr: this.ptr }
+    }
+
+    // 只有一个强引用时把值拿出来，否则原样返回
+    fn try_unwrap(this: Self) -> Result<T, Self> {
+        if MyRc::strong_count(&this) != 1 {
+            return Err(this);
+        }
+        let ptr = this.ptr;
+        mem::forget(this);
+        strong_cell(ptr).set(0);
+        let value = unsafe { ManuallyDrop::take(&mut *ptr::addr_of_mut!((*ptr.as_ptr()).value)) };
+        // 剩下的 MyWeak 再也升级不了
+        unsafe { release_weak(ptr) };
+        Ok(value)
+    }
+
+    // 没有其他强引用和弱引用时才能拿到可变引用
+    fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if MyRc::strong_count(this) == 1 && MyRc::weak_count(this) == 0 {
+            Some(unsafe { &mut *ptr::addr_of_mut!((*this.ptr.as_ptr()).value) })
+        } else {
+            None
+        }
+    }
+
+    // 写时复制：有人共享时先复制一份再修改，
+    // 只剩弱引用时也复制，原来的值随之释放，弱引用升级会失败
+    fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        if MyRc::get_mut(this).is_none() {
+            *this = MyRc::new((**this).clone());
+        }
+        MyRc::get_mut(this).unwrap()
+    }
+
+    fn as_ptr(this: &Self) -> *const T {
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).value) as *const T }
+    }
+}
+
+impl<T> Clone for MyRc<T> {
+    fn clone(&self) -> Self {
+        let strong = strong_cell(self.ptr);
+        strong.set(strong.get() + 1);
+        MyRc { ptr: self.ptr }
+    }
+}
+
+impl<T> Deref for MyRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // 只要还有强引用，值就一定活着
+        unsafe { &*ptr::addr_of!((*self.ptr.as_ptr()).value) }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MyRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for MyRc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T> Drop for MyRc<T> {
+    fn drop(&mut self) {
+        let strong = strong_cell(self.ptr);
+        strong.set(strong.get() - 1);
+        if strong.get() == 0 {
+            unsafe {
+                ManuallyDrop::drop(&mut *ptr::addr_of_mut!((*self.ptr.as_ptr()).value));
+                release_weak(self.ptr);
+            }
+        }
+    }
+}
+
+impl<T> MyWeak<T> {
+    // 值还活着就得到一个新的强引用
+    fn upgrade(&self) -> Option<MyRc<T>> {
+        let strong = strong_cell(self.ptr);
+        if strong.get() == 0 {
+            return None;
+        }
+        strong.set(strong.get() + 1);
+        Some(MyRc { ptr: self.ptr })
+    }
+
+    fn strong_count(&self) -> usize {
+        strong_cell(self.ptr).get()
+    }
+
+    fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<T> Clone for MyWeak<T> {
+    fn clone(&self) -> Self {
+        let weak = weak_cell(self.ptr);
+        weak.set(weak.get() + 1);
+        MyWeak { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for MyWeak<T> {
+    fn drop(&mut self) {
+        unsafe { release_weak(self.ptr) };
+    }
+}
+
+// 记录 drop 次数
+struct DropFlag(Rc<Cell<usize>>);
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+fn my_rc_test() {
+    let a = MyRc::new(String::from("hello, world"));
+    let b = MyRc::clone(&a);
+    assert_eq!(MyRc::strong_count(&a), 2);
+    assert!(MyRc::ptr_eq(&a, &b));
+    assert!(!MyRc::ptr_eq(&a, &MyRc::new(String::from("hello, world"))));
+    assert_eq!(b.len(), 12);
+
+    // 共享时拿不出来
+    let a = MyRc::try_unwrap(a).unwrap_err();
+    drop(b);
+    assert_eq!(MyRc::try_unwrap(a), Ok(String::from("hello, world")));
+
+    // 写时复制
+    let mut x = MyRc::new(vec![1, 2]);
+    let y = MyRc::clone(&x);
+    MyRc::make_mut(&mut x).push(3);
+    assert_eq!(*x, vec![1, 2, 3]);
+    assert_eq!(*y, vec![1, 2]);
+    assert!(!MyRc::ptr_eq(&x, &y));
+    // 已经独占，不再复制
+    let before = MyRc::as_ptr(&x);
+    MyRc::make_mut(&mut x).push(4);
+    assert_eq!(MyRc::as_ptr(&x), before);
+    assert_eq!(MyRc::get_mut(&mut x).map(|v| v.len()), Some(4));
+
+    // 最后一个强引用释放时 drop 值，且只 drop 一次
+    let drops = Rc::new(Cell::new(0));
+    let flag = MyRc::new(DropFlag(Rc::clone(&drops)));
+    let flags: Vec<_> = (0..5).map(|_| MyRc::clone(&flag)).collect();
+    drop(flag);
+    assert_eq!(drops.get(), 0);
+    drop(flags);
+    assert_eq!(drops.get(), 1);
+    let unwrapped = MyRc::try_unwrap(MyRc::new(DropFlag(Rc::clone(&drops))));
+    assert_eq!(drops.get(), 1);
+    drop(unwrapped);
+    assert_eq!(drops.get(), 2);
+}
+
+// Owner 用弱引用指向 Gadget，Gadget 用强引用指向 Owner，不会形成环
+struct MyOwner {
+    name: String,
+    gadgets: RefCell<Vec<MyWeak<MyGadget>>>,
+}
+struct MyGadget {
+    id: i32,
+    owner: MyRc<MyOwner>,
+}
+
+fn my_weak_test() {
+    let owner = MyRc::new(MyOwner { name: "Gadget man".to_string(), gadgets: RefCell::new(Vec::new()) });
+    let gadget1 = MyRc::new(MyGadget { id: 1, owner: MyRc::clone(&owner) });
+    let gadget2 = MyRc::new(MyGadget { id: 2, owner: MyRc::clone(&owner) });
+    owner.gadgets.borrow_mut().push(MyRc::downgrade(&gadget1));
+    owner.gadgets.borrow_mut().push(MyRc::downgrade(&gadget2));
+    assert_eq!(MyRc::strong_count(&owner), 3);
+    assert_eq!(MyRc::weak_count(&gadget1), 1);
+
+    let ids: Vec<i32> = owner.gadgets.borrow().iter().filter_map(|g| g.upgrade()).map(|g| g.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+    assert_eq!(gadget2.owner.name, "Gadget man");
+
+    // gadget1 被释放后，弱引用升级失败，但 RcBox 要等弱引用也释放后才回收
+    drop(gadget1);
+    let alive: Vec<i32> = owner.gadgets.borrow().iter().filter_map(|g| g.upgrade()).map(|g| g.id).collect();
+    assert_eq!(alive, vec![2]);
+    assert_eq!(owner.gadgets.borrow()[0].strong_count(), 0);
+    assert_eq!(MyRc::strong_count(&owner), 2);
+
+    // 只剩弱引用时 make_mut 也会复制
+    let mut v = MyRc::new(1);
+    let w = MyRc::downgrade(&v);
+    *MyRc::make_mut(&mut v) += 1;
+    assert_eq!(*v, 2);
+    assert!(w.upgrade().is_none());
+    let w2 = MyRc::downgrade(&v);
+    assert!(w2.ptr_eq(&w2.clone()));
+    assert!(MyRc::ptr_eq(&w2.upgrade().unwrap(), &v));
+}
+
+// 环检测（仅 debug 模式）
+// 注册过的对象需要实现 Trace，告诉回收器自己直接持有哪些 MyRc，以及怎样断开它们。
+// 试删除（trial deletion）：把每个对象的强引用计数减去来自其他注册对象的引用，
+// 剩下还大于 0 的说明有外部引用，是根；从根出发能到达的都活着，其余的只被环引用，是垃圾。
+// 回收器只持有 MyWeak，不影响对象的释放
+struct Visitor {
+    edges: Vec<usize>,
+}
+impl Visitor {
+    fn visit<T>(&mut self, rc: &MyRc<T>) {
+        self.edges.push(rc.ptr.as_ptr() as usize);
+    }
+}
+
+trait Trace {
+    fn trace(&self, visitor: &mut Visitor);
+    // 丢掉自己持有的 MyRc，用于打破环
+    fn break_cycle(&self);
+}
+
+// 擦除类型后保存在注册表里
+trait Tracked {
+    fn addr(&self) -> usize;
+    fn strong(&self) -> usize;
+    fn edges(&self) -> Vec<usize>;
+    // 返回一个强引用，保证断开过程中对象还活着
+    fn pin(&self) -> Option<Box<dyn Any>>;
+    fn break_cycle(&self);
+}
+
+// 注册表持有的弱引用，创建和释放时同时维护 tracked，对外看不到它
+struct Registered<T>(MyWeak<T>);
+impl<T> Registered<T> {
+    fn new(rc: &MyRc<T>) -> Self {
+        let tracked = tracked_cell(rc.ptr);
+        tracked.set(tracked.get() + 1);
+        Registered(MyRc::downgrade(rc))
+    }
+}
+impl<T> Drop for Registered<T> {
+    // 先减 tracked，里面的 MyWeak 随后释放，RcBox 可能在那时被回收
+    fn drop(&mut self) {
+        let tracked = tracked_cell(self.0.ptr);
+        tracked.set(tracked.get() - 1);
+    }
+}
+
+impl<T: Trace + 'static> Tracked for Registered<T> {
+    fn addr(&self) -> usize {
+        self.0.ptr.as_ptr() as usize
+    }
+    fn strong(&self) -> usize {
+        self.0.strong_count()
+    }
+    fn edges(&self) -> Vec<usize> {
+        let mut visitor = Visitor { edges: Vec::new() };
+        if let Some(rc) = self.0.upgrade() {
+            rc.trace(&mut visitor);
+        }
+        visitor.edges
+    }
+    fn pin(&self) -> Option<Box<dyn Any>> {
+        self.0.upgrade().map(|rc| Box::new(rc) as Box<dyn Any>)
+    }
+    fn break_cycle(&self) {
+        if let Some(rc) = self.0.upgrade() {
+            rc.break_cycle();
+        }
+    }
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Vec<Box<dyn Tracked>>> = RefCell::new(Vec::new());
+}
+
+impl<T: Trace + 'static> MyRc<T> {
+    // release 模式下什么也不做；注册与否都不影响 weak_count
+    // 重复注册会被忽略：注册表持有弱引用，RcBox 不会被释放，地址也就不会被别的对象复用
+    fn register(this: &Self) {
+        if cfg!(debug_assertions) {
+            REGISTRY.with(|r| {
+                let mut r = r.borrow_mut();
+                let addr = this.ptr.as_ptr() as usize;
+                if !r.iter().any(|o| o.addr() == addr) {
+                    r.push(Box::new(Registered::new(this)));
+                }
+            });
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct CycleReport {
+    // 参与检查的存活对象
+    scanned: usize,
+    // 只被环引用着的对象
+    garbage: usize,
+}
+
+// 找出只被环引用的对象，break_cycles 为 true 时断开它们
+fn scan_cycles(break_cycles: bool) -> CycleReport {
+    // 先清掉已经释放的对象
+    let objects: Vec<Box<dyn Tracked>> = REGISTRY.with(|r| {
+        let mut r = r.borrow_mut();
+        r.retain(|o| o.strong() > 0);
+        mem::take(&mut *r)
+    });
+    let index: HashMap<usize, usize> = objects.iter().enumerate().map(|(i, o)| (o.addr(), i)).collect();
+    let edges: Vec<Vec<usize>> = objects.iter().map(|o| o.edges().into_iter().filter_map(|a| index.get(&a).copied()).collect()).collect();
+
+    // 试删除：减掉内部引用
+    let mut refs: Vec<usize> = objects.iter().map(|o| o.strong()).collect();
+    for targets in &edges {
+        for &t in targets {
+            refs[t] -= 1;
+        }
+    }
+    // 从有外部引用的对象出发标记存活
+    let mut alive = vec![false; objects.len()];
+    let mut stack: Vec<usize> = (0..objects.len()).filter(|&i| refs[i] > 0).collect();
+    while let Some(i) = stack.pop() {
+        if !mem::replace(&mut alive[i], true) {
+            stack.extend(edges[i].iter().copied());
+        }
+    }
+    let garbage: Vec<usize> = (0..objects.len()).filter(|&i| !alive[i]).collect();
+    let report = CycleReport { scanned: objects.len(), garbage: garbage.len() };
+
+    if break_cycles {
+        // 先全部断开再统一释放强引用，避免释放到一半去访问已经 drop 的对象
+        let pinned: Vec<Box<dyn Any>> = garbage.iter().filter_map(|&i| objects[i].pin()).collect();
+        for &i in &garbage {
+            objects[i].break_cycle();
+        }
+        drop(pinned);
+    }
+    // 放回注册表，释放过程中可能又注册了新对象
+    REGISTRY.with(|r| {
+        let mut r = r.borrow_mut();
+        let added = mem::take(&mut *r);
+        *r = objects;
+        r.extend(added);
+        r.retain(|o| o.strong() > 0);
+    });
+    report
+}
+
+fn detect_cycles() -> CycleReport {
+    scan_cycles(false)
+}
+
+fn collect_cycles() -> CycleReport {
+    scan_cycles(true)
+}
+
+// 互相用强引用指向对方的节点，会形成环
+struct GraphNode {
+    name: String,
+    links: RefCell<Vec<MyRc<GraphNode>>>,
+    _flag: DropFlag,
+}
+impl GraphNode {
+    fn new(name: &str, drops: &Rc<Cell<usize>>) -> MyRc<GraphNode> {
+        let node = MyRc::new(GraphNode { name: name.to_string(), links: RefCell::new(Vec::new()), _flag: DropFlag(Rc::clone(drops)) });
+        MyRc::register(&node);
+        node
+    }
+
+    fn link(&self, to: &MyRc<GraphNode>) {
+        self.links.borrow_mut().push(MyRc::clone(to));
+    }
+}
+impl Trace for GraphNode {
+    fn trace(&self, visitor: &mut Visitor) {
+        for link in self.links.borrow().iter() {
+            visitor.visit(link);
+        }
+    }
+    fn break_cycle(&self) {
+        self.links.borrow_mut().clear();
+    }
+}
+
+// 不会形成环的对象，用来检查注册后的写时复制
+#[derive(Clone)]
+struct Leaf(Vec<i32>);
+impl Trace for Leaf {
+    fn trace(&self, visitor: &mut Visitor) {}
+    fn break_cycle(&self) {}
+}
+
+fn cycle_collector_test() {
+    // 注册表的引用不算弱引用，注册过的对象 make_mut 原地修改，debug 和 release 下一样
+    let mut leaf = MyRc::new(Leaf(vec![1]));
+    MyRc::register(&leaf);
+    assert_eq!(MyRc::weak_count(&leaf), 0);
+    assert!(MyRc::get_mut(&mut leaf).is_some());
+    let before = MyRc::as_ptr(&leaf);
+    MyRc::make_mut(&mut leaf).0.push(2);
+    assert_eq!(MyRc::as_ptr(&leaf), before);
+    assert_eq!(leaf.0, vec![1, 2]);
+    // 用户自己的弱引用照常计数
+    let w = MyRc::downgrade(&leaf);
+    assert_eq!(MyRc::weak_count(&leaf), 1);
+    assert!(MyRc::get_mut(&mut leaf).is_none());
+    drop((w, leaf));
+
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let drops = Rc::new(Cell::new(0));
+    // a -> b -> c -> a 形成环，d -> a 只是指向环
+    let a = GraphNode::new("a", &drops);
+    let b = GraphNode::new("b", &drops);
+    let c = GraphNode::new("c", &drops);
+    let d = GraphNode::new("d", &drops);
+    a.link(&b);
+    b.link(&c);
+    c.link(&a);
+    d.link(&a);
+    // 自己指向自己
+    let e = GraphNode::new("e", &drops);
+    e.link(&e);
+
+    // 外面还持有引用时都是活的
+    assert_eq!(detect_cycles(), CycleReport { scanned: 5, garbage: 0 });
+
+    drop((a, b, c, e));
+    // 这几个都还被 d 间接引用着，或者自己引用自己
+    assert_eq!(drops.get(), 0);
+    assert_eq!(detect_cycles(), CycleReport { scanned: 5, garbage: 1 });
+
+    drop(d);
+    // d 被释放了，a、b、c 成了孤立的环
+    assert_eq!(drops.get(), 1);
+    assert_eq!(detect_cycles(), CycleReport { scanned: 4, garbage: 4 });
+    assert_eq!(collect_cycles(), CycleReport { scanned: 4, garbage: 4 });
+    assert_eq!(drops.get(), 5);
+    assert_eq!(detect_cycles(), CycleReport::default());
+
+    // 被环外的对象引用的环不会被回收
+    let x = GraphNode::new("x", &drops);
+    let y = GraphNode::new("y", &drops);
+    x.link(&y);
+    y.link(&x);
+    let held = MyRc::clone(&y);
+    drop((x, y));
+    assert_eq!(collect_cycles(), CycleReport { scanned: 2, garbage: 0 });
+    assert_eq!(held.links.borrow()[0].name, "x");
+    drop(held);
+    assert_eq!(collect_cycles().garbage, 2);
+    assert_eq!(drops.get(), 7);
+
+    // 重复注册只算一个对象，环照样能被回收
+    let p = GraphNode::new("p", &drops);
+    let q = GraphNode::new("q", &drops);
+    MyRc::register(&p);
+    MyRc::register(&p);
+    p.link(&q);
+    q.link(&p);
+    assert_eq!(detect_cycles(), CycleReport { scanned: 2, garbage: 0 });
+    drop((p, q));
+    assert_eq!(collect_cycles(), CycleReport { scanned: 2, garbage: 2 });
+    assert_eq!(drops.get(), 9);
+}
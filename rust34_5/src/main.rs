@@ -3,6 +3,7 @@ use std::cell::{Cell,RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
+use rust34_1_heap::{measure, HeapSize, TrackingAlloc};
 /* Cell RefCell
 可以在拥有不可变引用的同时修改目标数据，对于正常的代码实现来说，是不可能的
 （要么一个可变借用，要么多个不可变借用）。
@@ -136,6 +137,20 @@ impl<'a> CellRef<'a> {
     fn new(a: &'a str, b: &'a mut i32, c: Vec<String>, d: HashMap<i32, i32>) -> Self { Self { a, b, c, d } }
 }
 
+// 堆内存统计用 rust34_1_heap 里的 HeapSize，装上它的分配器后可以和实际分配的字节数对比
+#[global_allocator]
+static GLOBAL: TrackingAlloc = TrackingAlloc;
+
+// a、b 是借来的，内存归别人，不算在 CellRef 里
+impl HeapSize for CellRef<'_> {
+    fn heap_size_shallow(&self) -> usize {
+        0
+    }
+    fn heap_size_deep(&self) -> usize {
+        self.c.heap_size_deep() + self.d.heap_size_deep()
+    }
+}
+
 fn cell_ref_test() {
     let mut hm = HashMap::new();
     hm.insert(3, 4);
@@ -143,6 +158,17 @@ fn cell_ref_test() {
     let (a, mut b,c,d) = ("a".to_string(), 3, vc , hm);
     let cr = CellRef::new(&a, &mut b, c, d);
     println!("{:?}", cr);
+    // Vec<String> 的统计是精确的，HashMap 只是下限
+    let (c, stats) = measure(|| (0..10).map(|i| format!("item{}", i)).collect::<Vec<String>>());
+    assert_eq!(c.heap_size_deep() as isize, stats.live_bytes());
+    let (d, stats) = measure(|| (0..10).map(|i| (i, i * i)).collect::<HashMap<i32, i32>>());
+    assert!(d.heap_size_deep() > 0);
+    assert!(d.heap_size_deep() as isize <= stats.live_bytes());
+    let (mut key, mut value) = ("k".to_string(), 0);
+    let (c_size, d_size) = (c.heap_size_deep(), d.heap_size_deep());
+    let (measured, stats) = measure(|| CellRef::new(&key, &mut value, c, d));
+    assert_eq!(stats.allocs, 0);
+    assert_eq!(measured.heap_size_deep(), c_size + d_size);
 
     let s = Rc::new(RefCell::new(cr));
     let s1 = Rc::clone(&s);